@@ -0,0 +1,17 @@
+//! Prints the script one sample runs, for capturing test fixtures:
+//!
+//! ```text
+//! cargo run -q --example sample_script | ssh host sh > sample1.out
+//! ```
+
+use remote_monitoring::batch;
+use remote_monitoring::collector::default_collectors;
+
+fn main() {
+    let collectors = default_collectors();
+    let sections = collectors
+        .iter()
+        .map(|collector| (collector.name(), collector.command()))
+        .collect::<Vec<_>>();
+    println!("{}", batch::script(&sections));
+}
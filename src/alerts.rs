@@ -0,0 +1,795 @@
+use remote_monitoring::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io::Write;
+use std::iter::Peekable;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::IntoIter;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Metrics a rule can refer to: name, whether it takes a `["key"]`, and its fields.
+const METRICS: &[(&str, bool, &[&str])] = &[
+    (
+        "cpu",
+        false,
+        &[
+            "user", "nice", "system", "idle", "iowait", "irq", "softirq", "steal", "guest", "busy",
+        ],
+    ),
+    ("load1", false, &[]),
+    ("load5", false, &[]),
+    ("load15", false, &[]),
+    ("cores", false, &[]),
+    ("uptime", false, &[]),
+    ("procs", false, &["running", "total"]),
+    (
+        "mem",
+        false,
+        &[
+            "used_pct",
+            "used",
+            "free",
+            "available",
+            "total",
+            "buffers",
+            "cached",
+            "shared",
+            "slab",
+            "dirty",
+            "writeback",
+            "committed",
+            "commit_limit",
+            "hugepages_free",
+            "hugepages_total",
+        ],
+    ),
+    ("swap", false, &["used_pct", "used", "free", "total"]),
+    (
+        "fs",
+        true,
+        &["used_pct", "inodes_pct", "free", "used", "size"],
+    ),
+    (
+        "disk",
+        true,
+        &["util", "await", "read", "write", "read_iops", "write_iops"],
+    ),
+    ("net", true, &["rx", "tx", "rx_packets", "tx_packets"]),
+];
+
+/// The `--alerts` file:
+///
+/// ```toml
+/// on_alert = "mail -s rsshtop oncall@example.com"
+///
+/// [[rule]]
+/// condition = "cpu.iowait > 30 for 2m"
+/// clear = 20
+///
+/// [[rule]]
+/// name = "root full"
+/// condition = 'fs["/"].used_pct > 90'
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AlertFile {
+    on_alert: Option<String>,
+    #[serde(default)]
+    rule: Vec<RuleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    name: Option<String>,
+    condition: String,
+    /// While firing, the right-hand side is replaced by this value, so a rule
+    /// does not flap around its threshold.
+    clear: Option<f64>,
+}
+
+pub struct AlertConfig {
+    rules: Vec<Rule>,
+    /// Run with `sh -c` for every alert that fires or resolves, the alert as JSON on stdin.
+    on_alert: Option<String>,
+}
+
+struct Rule {
+    name: String,
+    text: String,
+    left: Expr,
+    op: Comparison,
+    right: Expr,
+    hold: Duration,
+    clear: Option<f64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+enum Expr {
+    Number(f64),
+    Metric {
+        name: String,
+        key: Option<String>,
+        field: Option<String>,
+    },
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+/// What the `on_alert` hook receives.
+#[derive(Serialize)]
+pub struct Alert {
+    pub host: String,
+    pub rule: String,
+    pub condition: String,
+    /// "firing" or "resolved".
+    pub state: &'static str,
+    /// Null when a rule resolves because its metrics are missing from the sample.
+    pub value: Option<f64>,
+    pub threshold: Option<f64>,
+    /// RFC 3339, UTC.
+    pub timestamp: String,
+}
+
+/// Evaluates the rules against the samples of one host.
+pub struct Alerts {
+    config: Arc<AlertConfig>,
+    states: Vec<RuleState>,
+}
+
+#[derive(Clone, Copy)]
+enum RuleState {
+    Inactive,
+    /// The condition holds since then, but not yet for the rule's duration.
+    Pending(Instant),
+    Firing,
+}
+
+pub fn load(path: &str) -> Result<AlertConfig, Box<dyn Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let file: AlertFile =
+        toml::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    let rules = file
+        .rule
+        .into_iter()
+        .map(|entry| {
+            let mut rule = parse_rule(&entry.condition)
+                .map_err(|e| format!("{}: rule '{}': {}", path, entry.condition, e))?;
+            rule.name = entry.name.unwrap_or_else(|| rule.text.clone());
+            rule.clear = entry.clear;
+            Ok(rule)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(AlertConfig {
+        rules,
+        on_alert: file.on_alert,
+    })
+}
+
+impl Alerts {
+    pub fn new(config: Arc<AlertConfig>) -> Self {
+        let states = vec![RuleState::Inactive; config.rules.len()];
+        Alerts { config, states }
+    }
+
+    /// Moves every rule on by one sample and returns the alerts that fired or
+    /// resolved. Rules whose metrics are missing from the sample, or come from a
+    /// collector that failed, start over: a firing one resolves.
+    pub fn evaluate(&mut self, host: &str, stats: &Stats) -> Vec<Alert> {
+        self.evaluate_at(host, stats, Instant::now())
+    }
+
+    fn evaluate_at(&mut self, host: &str, stats: &Stats, now: Instant) -> Vec<Alert> {
+        let mut changes = Vec::new();
+        for (rule, state) in self.config.rules.iter().zip(self.states.iter_mut()) {
+            let (value, threshold) = (rule.left.value(stats), rule.right.value(stats));
+            let (next, change) = match (*state, value, threshold) {
+                (RuleState::Firing, Some(value), Some(threshold)) => {
+                    let threshold = rule.clear.unwrap_or(threshold);
+                    if rule.op.holds(value, threshold) {
+                        (RuleState::Firing, None)
+                    } else {
+                        (RuleState::Inactive, Some("resolved"))
+                    }
+                }
+                (RuleState::Firing, _, _) => (RuleState::Inactive, Some("resolved")),
+                (_, Some(value), Some(threshold)) if rule.op.holds(value, threshold) => {
+                    match *state {
+                        RuleState::Pending(since) if now.duration_since(since) < rule.hold => {
+                            (RuleState::Pending(since), None)
+                        }
+                        RuleState::Inactive if !rule.hold.is_zero() => {
+                            (RuleState::Pending(now), None)
+                        }
+                        _ => (RuleState::Firing, Some("firing")),
+                    }
+                }
+                _ => (RuleState::Inactive, None),
+            };
+            *state = next;
+            if let Some(alert_state) = change {
+                let threshold = match alert_state {
+                    "resolved" => rule.clear.or(threshold),
+                    _ => threshold,
+                };
+                changes.push(Alert {
+                    host: host.to_string(),
+                    rule: rule.name.clone(),
+                    condition: rule.text.clone(),
+                    state: alert_state,
+                    value,
+                    threshold,
+                    timestamp: OffsetDateTime::now_utc()
+                        .format(&Rfc3339)
+                        .unwrap_or_default(),
+                });
+            }
+        }
+        changes
+    }
+
+    /// Names of the rules currently firing.
+    pub fn firing(&self) -> Vec<String> {
+        self.config
+            .rules
+            .iter()
+            .zip(&self.states)
+            .filter(|(_, state)| matches!(state, RuleState::Firing))
+            .map(|(rule, _)| rule.name.clone())
+            .collect()
+    }
+
+    /// Starts the `on_alert` hook for `alert`. The hook runs in the background,
+    /// only failing to start it is an error.
+    pub fn notify(&self, alert: &Alert) -> Result<(), Box<dyn Error>> {
+        let Some(command) = &self.config.on_alert else {
+            return Ok(());
+        };
+        let mut child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("on_alert: {}", e))?;
+        let mut json = serde_json::to_string(alert)?;
+        json.push('\n');
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        thread::spawn(move || {
+            let _ = stdin.write_all(json.as_bytes());
+            drop(stdin);
+            let _ = child.wait();
+        });
+        Ok(())
+    }
+}
+
+impl Comparison {
+    fn holds(self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+        }
+    }
+}
+
+impl Expr {
+    fn value(&self, stats: &Stats) -> Option<f64> {
+        match self {
+            Expr::Number(number) => Some(*number),
+            Expr::Negate(expr) => expr.value(stats).map(|value| -value),
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.value(stats)?, right.value(stats)?);
+                match op {
+                    '+' => Some(left + right),
+                    '-' => Some(left - right),
+                    '*' => Some(left * right),
+                    _ if right == 0.0 => None,
+                    _ => Some(left / right),
+                }
+            }
+            Expr::Metric { name, key, field } => {
+                metric_value(stats, name, key.as_deref(), field.as_deref().unwrap_or(""))
+            }
+        }
+    }
+}
+
+/// Collectors a metric is read from.
+fn metric_sections(name: &str) -> &'static [&'static str] {
+    match name {
+        "cpu" | "cores" => &["stat"],
+        "load1" | "load5" | "load15" | "procs" => &["loadavg"],
+        "uptime" => &["uptime"],
+        "mem" | "swap" => &["meminfo"],
+        "fs" => &["filesystems"],
+        "disk" => &["disks"],
+        "net" => &["interfaces", "net/dev"],
+        _ => &[],
+    }
+}
+
+/// The metric's value, or None when it is missing or its collector failed and
+/// left only the figures of an earlier sample.
+fn metric_value(stats: &Stats, name: &str, key: Option<&str>, field: &str) -> Option<f64> {
+    if stats.failure(metric_sections(name)).is_some() {
+        return None;
+    }
+    let percent = |part: u64, total: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            part as f64 / total as f64 * 100.0
+        }
+    };
+    let value = match (name, field) {
+        ("cpu", "busy") => stats.cpu.busy() as f64,
+        ("cpu", "user") => stats.cpu.user as f64,
+        ("cpu", "nice") => stats.cpu.nice as f64,
+        ("cpu", "system") => stats.cpu.system as f64,
+        ("cpu", "idle") => stats.cpu.idle as f64,
+        ("cpu", "iowait") => stats.cpu.iowait as f64,
+        ("cpu", "irq") => stats.cpu.irq as f64,
+        ("cpu", "softirq") => stats.cpu.soft_irq as f64,
+        ("cpu", "steal") => stats.cpu.steal as f64,
+        ("cpu", "guest") => stats.cpu.guest as f64,
+        ("load1", _) => stats.load1.parse().ok()?,
+        ("load5", _) => stats.load5.parse().ok()?,
+        ("load15", _) => stats.load10.parse().ok()?,
+        ("cores", _) => stats.cores.len().max(1) as f64,
+        ("uptime", _) => stats.uptime,
+        ("procs", "running") => stats.running_procs.trim().parse().ok()?,
+        ("procs", "total") => stats.total_procs.trim().parse().ok()?,
+        ("mem", "used_pct") => stats.mem_usage() as f64,
+        ("mem", "used") => stats.memory.used() as f64,
+        ("mem", "free") => stats.memory.free() as f64,
+        ("mem", "available") => stats.memory.available() as f64,
+        ("mem", "total") => stats.memory.total() as f64,
+        ("mem", "buffers") => stats.memory.buffers() as f64,
+        ("mem", "cached") => stats.memory.cached() as f64,
+        ("mem", "shared") => stats.memory.shared() as f64,
+        ("mem", "slab") => stats.memory.slab() as f64,
+        ("mem", "dirty") => stats.memory.dirty() as f64,
+        ("mem", "writeback") => stats.memory.writeback() as f64,
+        ("mem", "committed") => stats.memory.committed() as f64,
+        ("mem", "commit_limit") => stats.memory.commit_limit() as f64,
+        ("mem", "hugepages_free") => stats.memory.huge_pages_free() as f64,
+        ("mem", "hugepages_total") => stats.memory.huge_pages_total() as f64,
+        ("swap", "used_pct") => percent(stats.memory.swap_used(), stats.memory.swap_total()),
+        ("swap", "used") => stats.memory.swap_used() as f64,
+        ("swap", "free") => stats.memory.swap_free() as f64,
+        ("swap", "total") => stats.memory.swap_total() as f64,
+        ("fs", field) => {
+            let fs = stats
+                .fs_infos
+                .iter()
+                .find(|fs| Some(fs.mount_point.as_str()) == key)?;
+            match field {
+                "used_pct" => fs.used_percent as f64,
+                "inodes_pct" => fs.inodes_percent()? as f64,
+                "free" => fs.free as f64,
+                "used" => fs.used as f64,
+                _ => fs.size as f64,
+            }
+        }
+        ("disk", field) => {
+            let disk = stats
+                .disks
+                .iter()
+                .find(|disk| Some(disk.name.as_str()) == key)?;
+            match field {
+                "util" => disk.util as f64,
+                "await" => disk.await_ms,
+                "read" => disk.read_rate,
+                "write" => disk.write_rate,
+                "read_iops" => disk.read_iops,
+                _ => disk.write_iops,
+            }
+        }
+        ("net", field) => {
+            let intf = stats.net_intf.get(key?)?;
+            match field {
+                "rx" => intf.rx_rate,
+                "tx" => intf.tx_rate,
+                "rx_packets" => intf.rx_packet_rate,
+                _ => intf.tx_packet_rate,
+            }
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Parses `<expr> <comparison> <expr> [for <duration>]`.
+fn parse_rule(text: &str) -> Result<Rule, String> {
+    let text = text.trim();
+    let (condition, hold) = match text.rsplit_once(" for ") {
+        Some((condition, hold)) => (condition, parse_duration(hold.trim())?),
+        None if text.ends_with(" for") => {
+            return Err(String::from("expected a duration like 2m after 'for'"))
+        }
+        None => (text, Duration::ZERO),
+    };
+    let mut parser = Parser {
+        tokens: tokenize(condition)?.into_iter().peekable(),
+    };
+    let left = parser.sum()?;
+    let op = match parser.next() {
+        Some(Token::Comparison(op)) => op,
+        Some(token) => return Err(format!("expected a comparison, found {}", token)),
+        None => return Err(String::from("expected a comparison like > or <=")),
+    };
+    let right = parser.sum()?;
+    if let Some(token) = parser.next() {
+        return Err(format!("unexpected {}", token));
+    }
+    Ok(Rule {
+        name: String::new(),
+        text: text.to_string(),
+        left,
+        op,
+        right,
+        hold,
+        clear: None,
+    })
+}
+
+/// `90`, `90s`, `2m` or `1h`.
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let (number, unit) =
+        text.split_at(text.find(|c: char| c.is_alphabetic()).unwrap_or(text.len()));
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => {
+            return Err(format!(
+                "invalid duration '{}', expected e.g. 30s, 2m or 1h",
+                text
+            ))
+        }
+    };
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration '{}', expected e.g. 30s, 2m or 1h", text))?;
+    Ok(Duration::from_secs(number * seconds))
+}
+
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Symbol(char),
+    Comparison(Comparison),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "'{}'", number),
+            Token::Ident(ident) => write!(f, "'{}'", ident),
+            Token::Str(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::Comparison(_) => write!(f, "a second comparison"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        i += 1;
+        match c {
+            c if c.is_whitespace() => {}
+            '0'..='9' => {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number = chars[start..i].iter().collect::<String>();
+                tokens.push(Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid number '{}'", number))?,
+                ));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            '"' | '\'' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|other| *other == c)
+                    .ok_or("unterminated string")?;
+                tokens.push(Token::Str(chars[i..i + end].iter().collect()));
+                i += end + 1;
+            }
+            '<' | '>' | '=' | '!' => {
+                let equals = chars.get(i) == Some(&'=');
+                if equals {
+                    i += 1;
+                }
+                let op = match (c, equals) {
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('<', false) => Comparison::Less,
+                    ('<', true) => Comparison::LessOrEqual,
+                    ('=', true) => Comparison::Equal,
+                    ('!', true) => Comparison::NotEqual,
+                    _ => return Err(format!("unexpected '{}'", c)),
+                };
+                tokens.push(Token::Comparison(op));
+            }
+            '+' | '-' | '*' | '/' | '(' | ')' | '[' | ']' | '.' => tokens.push(Token::Symbol(c)),
+            _ => return Err(format!("unexpected '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+
+    fn peek_symbol(&mut self, symbols: &[char]) -> Option<char> {
+        match self.tokens.peek() {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(found)) if found == symbol => Ok(()),
+            Some(token) => Err(format!("expected '{}', found {}", symbol, token)),
+            None => Err(format!("expected '{}'", symbol)),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.peek_symbol(&['+', '-']) {
+            self.next();
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.atom()?;
+        while let Some(op) = self.peek_symbol(&['*', '/']) {
+            self.next();
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.atom()?));
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Symbol('-')) => Ok(Expr::Negate(Box::new(self.atom()?))),
+            Some(Token::Symbol('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => self.metric(name),
+            Some(token) => Err(format!("expected a number or metric, found {}", token)),
+            None => Err(String::from("expected a number or metric")),
+        }
+    }
+
+    fn metric(&mut self, name: String) -> Result<Expr, String> {
+        let Some((_, keyed, fields)) = METRICS.iter().find(|(metric, _, _)| *metric == name) else {
+            return Err(format!("unknown metric '{}'", name));
+        };
+        let key = if *keyed {
+            self.expect('[')?;
+            let key = match self.next() {
+                Some(Token::Str(key)) => key,
+                _ => return Err(format!("expected a quoted name after {}[", name)),
+            };
+            self.expect(']')?;
+            Some(key)
+        } else {
+            None
+        };
+        let field = if fields.is_empty() {
+            None
+        } else {
+            self.expect('.')?;
+            match self.next() {
+                Some(Token::Ident(field)) if fields.contains(&field.as_str()) => Some(field),
+                _ => {
+                    return Err(format!(
+                        "expected one of {} after {}.",
+                        fields.join(", "),
+                        name
+                    ))
+                }
+            }
+        };
+        Ok(Expr::Metric { name, key, field })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use remote_monitoring::error::{MonitorError, SectionError};
+
+    fn alerts_for(condition: &str, clear: Option<f64>) -> Alerts {
+        let mut rule = parse_rule(condition).unwrap();
+        rule.name = rule.text.clone();
+        rule.clear = clear;
+        Alerts::new(Arc::new(AlertConfig {
+            rules: vec![rule],
+            on_alert: None,
+        }))
+    }
+
+    fn load(load1: f64) -> Stats {
+        let mut stats = Stats::default();
+        stats
+            .parse_load(&format!("{:.2} 0.50 0.25 1/100 4321", load1))
+            .unwrap();
+        stats
+    }
+
+    fn states(alerts: &[Alert]) -> Vec<&str> {
+        alerts.iter().map(|alert| alert.state).collect()
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule = parse_rule(r#"fs["/"].used_pct >= 90 - 5 for 2m"#).unwrap();
+        assert!(rule.op == Comparison::GreaterOrEqual);
+        assert_eq!(rule.hold, Duration::from_secs(120));
+        assert!(matches!(
+            &rule.left,
+            Expr::Metric { name, key: Some(key), field: Some(field) }
+                if name == "fs" && key == "/" && field == "used_pct"
+        ));
+        assert!(matches!(rule.right, Expr::Binary(_, '-', _)));
+
+        let rule = parse_rule("(mem.used + swap.used) / mem.total * 100 < 50").unwrap();
+        assert!(rule.op == Comparison::Less);
+        assert_eq!(rule.hold, Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_malformed_rules() {
+        let error = |text| parse_rule(text).err().unwrap();
+        assert_eq!(error("load1 => 3"), "unexpected '='");
+        assert_eq!(
+            error("load1 > > 3"),
+            "expected a number or metric, found a second comparison"
+        );
+        assert_eq!(error("load1 3"), "expected a comparison, found '3'");
+        assert_eq!(error("load1 ~ 3"), "unexpected '~'");
+        assert_eq!(error("load7 > 3"), "unknown metric 'load7'");
+        assert_eq!(
+            error("swap.cached > 3"),
+            "expected one of used_pct, used, free, total after swap."
+        );
+        assert_eq!(error("fs.used_pct > 3"), "expected '[', found '.'");
+        assert_eq!(
+            error("load1 > 3 for"),
+            "expected a duration like 2m after 'for'"
+        );
+        assert_eq!(
+            error("load1 > 3 for 2 weeks"),
+            "invalid duration '2 weeks', expected e.g. 30s, 2m or 1h"
+        );
+        assert_eq!(
+            error("load1 > 3 for m"),
+            "invalid duration 'm', expected e.g. 30s, 2m or 1h"
+        );
+    }
+
+    #[test]
+    fn fires_only_after_the_duration() {
+        let mut alerts = alerts_for("load1 > 2 for 1m", None);
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        assert!(alerts.evaluate_at("h", &load(3.0), at(0)).is_empty());
+        assert!(alerts.evaluate_at("h", &load(3.0), at(59)).is_empty());
+        assert!(alerts.firing().is_empty());
+        let fired = alerts.evaluate_at("h", &load(3.0), at(60));
+        assert_eq!(states(&fired), ["firing"]);
+        assert_eq!(fired[0].value, Some(3.0));
+        assert_eq!(fired[0].threshold, Some(2.0));
+        assert_eq!(alerts.firing(), ["load1 > 2 for 1m"]);
+
+        // Dropping below the threshold while pending starts the duration over.
+        let mut alerts = alerts_for("load1 > 2 for 1m", None);
+        assert!(alerts.evaluate_at("h", &load(3.0), at(0)).is_empty());
+        assert!(alerts.evaluate_at("h", &load(1.0), at(30)).is_empty());
+        assert!(alerts.evaluate_at("h", &load(3.0), at(40)).is_empty());
+        assert!(alerts.evaluate_at("h", &load(3.0), at(90)).is_empty());
+        assert_eq!(
+            states(&alerts.evaluate_at("h", &load(3.0), at(100))),
+            ["firing"]
+        );
+    }
+
+    #[test]
+    fn resolves_only_below_the_clear_threshold() {
+        let mut alerts = alerts_for("load1 > 2", Some(1.0));
+        let now = Instant::now();
+        assert_eq!(
+            states(&alerts.evaluate_at("h", &load(3.0), now)),
+            ["firing"]
+        );
+        assert!(alerts.evaluate_at("h", &load(1.5), now).is_empty());
+        assert_eq!(alerts.firing().len(), 1);
+        let resolved = alerts.evaluate_at("h", &load(0.5), now);
+        assert_eq!(states(&resolved), ["resolved"]);
+        assert_eq!(resolved[0].threshold, Some(1.0));
+        assert!(alerts.firing().is_empty());
+        assert!(alerts.evaluate_at("h", &load(1.5), now).is_empty());
+    }
+
+    #[test]
+    fn missing_metrics_resolve() {
+        let mut alerts = alerts_for("load1 > 2", None);
+        let now = Instant::now();
+        assert_eq!(
+            states(&alerts.evaluate_at("h", &load(3.0), now)),
+            ["firing"]
+        );
+
+        // The collector failed, leaving the figures of the last sample.
+        let mut stale = load(3.0);
+        stale.failed.push(SectionError {
+            section: "loadavg".to_string(),
+            error: MonitorError::parse("/proc/loadavg", 1, "truncated"),
+        });
+        let resolved = alerts.evaluate_at("h", &stale, now);
+        assert_eq!(states(&resolved), ["resolved"]);
+        assert_eq!(resolved[0].value, None);
+        assert!(alerts.firing().is_empty());
+        assert!(alerts.evaluate_at("h", &stale, now).is_empty());
+        assert_eq!(
+            states(&alerts.evaluate_at("h", &load(3.0), now)),
+            ["firing"]
+        );
+
+        let mut alerts = alerts_for(r#"fs["/data"].used_pct > 90"#, None);
+        assert!(alerts.evaluate_at("h", &Stats::default(), now).is_empty());
+        assert!(alerts.firing().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+/// Starts every section and its status line. Sections' own `@@name` markers
+/// are left alone, the prefix is longer.
+const SECTION: &str = "@@@rsshtop section ";
+const STATUS: &str = "@@@rsshtop status ";
+const STDERR: &str = "@@@rsshtop stderr ";
+
+/// Joins commands into one shell invocation. Each runs in a subshell between a
+/// marker naming it and a line with its exit status, followed by its stderr
+/// with every line marked. Stdout goes out through fd 3 while stderr is kept
+/// in a variable, which leaves `$?` the subshell's status. The script is kept
+/// on one line, so it can't produce marker lines itself when it shows up in
+/// the process table's command lines.
+pub fn script(sections: &[(&str, &str)]) -> String {
+    sections
+        .iter()
+        .map(|(name, command)| {
+            format!(
+                "echo '{SECTION}{name}'; \
+                 {{ rsshtop_err=$( ( {command} ) 2>&1 1>&3 3>&- ); rsshtop_status=$?; }} 3>&1; \
+                 echo \"{STATUS}$rsshtop_status\"; \
+                 [ -z \"$rsshtop_err\" ] || printf '%s\\n' \"$rsshtop_err\" | sed 's/^/{STDERR}/'"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub struct Section {
+    pub output: String,
+    /// `None` when the output ends before the status line.
+    pub status: Option<i32>,
+    pub stderr: String,
+}
+
+/// Splits the output of `script` by section name. `names` are the sections in
+/// the order they were passed to `script`; only a marker naming the next one
+/// starts a section, so a marker line in some output (a command line, say)
+/// can neither start a section out of order nor replace one.
+pub fn split(output: &str, names: &[&str]) -> HashMap<String, Section> {
+    let mut sections = HashMap::new();
+    let mut expected = names.iter();
+    let mut next = expected.next();
+    let mut current: Option<(String, Section)> = None;
+    for line in output.lines() {
+        if next.is_some_and(|name| line.strip_prefix(SECTION) == Some(*name)) {
+            if let Some((name, section)) = current.take() {
+                sections.insert(name, section);
+            }
+            let section = Section {
+                output: String::new(),
+                status: None,
+                stderr: String::new(),
+            };
+            current = next.map(|name| (name.to_string(), section));
+            next = expected.next();
+        } else if let Some((_, section)) = &mut current {
+            if let Some(stderr) = line.strip_prefix(STDERR) {
+                section.stderr.push_str(stderr);
+                section.stderr.push('\n');
+                continue;
+            }
+            match line.strip_prefix(STATUS) {
+                Some(status) if section.status.is_none() => {
+                    section.status = status.trim().parse().ok();
+                }
+                _ => {
+                    section.output.push_str(line);
+                    section.output.push('\n');
+                }
+            }
+        }
+    }
+    if let Some((name, section)) = current {
+        sections.insert(name, section);
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sections_with_status_and_stderr() {
+        let output = "@@@rsshtop section a\n\
+            one\n\
+            @@@rsshtop status 0\n\
+            @@@rsshtop section b\n\
+            @@@rsshtop status 127\n\
+            @@@rsshtop stderr sh: 1: ip: not found\n";
+        let sections = split(output, &["a", "b"]);
+        assert_eq!(sections["a"].output, "one\n");
+        assert_eq!(sections["a"].status, Some(0));
+        assert_eq!(sections["b"].output, "");
+        assert_eq!(sections["b"].status, Some(127));
+        assert_eq!(sections["b"].stderr, "sh: 1: ip: not found\n");
+    }
+
+    #[test]
+    fn missing_status_is_none() {
+        let sections = split("@@@rsshtop section a\npartial\n", &["a", "b"]);
+        assert_eq!(sections["a"].status, None);
+        assert!(!sections.contains_key("b"));
+    }
+
+    #[test]
+    fn markers_in_output_cannot_forge_sections() {
+        // A process whose argv holds marker lines, as the process table would
+        // show it if newlines in command lines were kept.
+        let output = "@@@rsshtop section meminfo\n\
+            MemTotal: 1000 kB\n\
+            @@@rsshtop status 0\n\
+            @@@rsshtop section processes\n\
+            ==> 4242/cmdline <==\n\
+            x\n\
+            @@@rsshtop section meminfo\n\
+            MemTotal: 1 kB\n\
+            @@@rsshtop section processes\n\
+            @@@rsshtop section uptime\n\
+            @@@rsshtop status 0\n";
+        let sections = split(output, &["meminfo", "processes"]);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections["meminfo"].output, "MemTotal: 1000 kB\n");
+        assert_eq!(sections["meminfo"].status, Some(0));
+        let processes = &sections["processes"];
+        assert!(processes.output.starts_with("==> 4242/cmdline <==\nx\n"));
+        assert!(processes
+            .output
+            .contains("@@@rsshtop section meminfo\nMemTotal: 1 kB\n"));
+        assert_eq!(processes.status, Some(0));
+    }
+
+    #[test]
+    fn sections_out_of_order_are_output() {
+        let output = "@@@rsshtop section b\n\
+            @@@rsshtop section a\n\
+            one\n\
+            @@@rsshtop status 0\n";
+        let sections = split(output, &["a", "b"]);
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections["a"].output, "one\n");
+    }
+}
@@ -1,17 +1,47 @@
+use crate::inventory;
+use crate::output::OutputFormat;
 use crate::sshconnect::*;
-use clap::Parser;
+use crate::target::parse_target;
+use clap::{Parser, Subcommand};
+use remote_monitoring::filesystems::FsFilter;
+use remote_monitoring::sshconfig::{self, HostConfig};
+use std::env;
+use std::error::Error;
+
+/// How many jump hosts' own ProxyJump entries are followed before giving up on
+/// a loop in ssh config.
+const MAX_JUMP_DEPTH: usize = 8;
 
 #[derive(Parser)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Optional argument
     /// PEM-encoded private key file to use (default: ~/.ssh/id_rsa if present)
     #[arg(short, long, value_name = "private_key_path")]
     pub private_key_file: Option<String>,
 
-    /// The SSH server to connect to
-    /// user@host:port
-    #[arg(long, value_name = "hostname")]
-    pub hostname: String,
+    /// The SSH server to connect to, repeat to monitor several hosts
+    /// [user@]host[:port], [user@][ipv6]:port, or a Host alias from ~/.ssh/config;
+    /// a bare localhost is this machine, as with --local
+    #[arg(
+        long,
+        value_name = "hostname",
+        required_unless_present_any = ["inventory", "local"]
+    )]
+    pub hostname: Vec<String>,
+
+    /// Optional argument
+    /// Monitor this machine too, reading /proc directly without SSH
+    #[arg(long)]
+    pub local: bool,
+
+    /// Optional argument
+    /// TOML file listing hosts to monitor together with --hostname
+    #[arg(long, value_name = "inventory")]
+    pub inventory: Option<String>,
 
     /// Optional argument
     /// Password for ssh connection
@@ -19,58 +49,375 @@ pub struct Cli {
     pub password: Option<String>,
 
     /// interval
-    #[arg(short, long, value_name = "interval")]
-    pub interval: usize,
+    #[arg(short, long, value_name = "interval", required = true)]
+    pub interval: Option<usize>,
+
+    /// Optional argument
+    /// Write machine-readable snapshots to stdout instead of the interactive display
+    #[arg(long, value_name = "format")]
+    pub output: Option<OutputFormat>,
+
+    /// Optional argument
+    /// Serve the metrics of all hosts at http://<address>/metrics in Prometheus text format
+    #[arg(long, value_name = "address", conflicts_with = "output")]
+    pub serve_prometheus: Option<String>,
+
+    /// Optional argument
+    /// TOML file with alert rules and an on_alert command
+    #[arg(long, value_name = "file")]
+    pub alerts: Option<String>,
+
+    /// Optional argument
+    /// Append every sample to this file (NDJSON) for the replay subcommand
+    #[arg(long, value_name = "file")]
+    pub record: Option<String>,
+
+    /// Optional argument
+    /// Print plain text each tick instead of the full-screen interface
+    #[arg(long)]
+    pub plain: bool,
+
+    /// Optional argument
+    /// Fail instead of asking when the host key is not in ~/.ssh/known_hosts
+    #[arg(long)]
+    pub refuse_unknown_hosts: bool,
+
+    /// Optional argument
+    /// OpenSSH client config file to read (default: ~/.ssh/config)
+    #[arg(short = 'F', long, value_name = "ssh_config")]
+    pub ssh_config: Option<String>,
+
+    /// Optional argument
+    /// Jump host(s) to connect through, user@host:port, comma separated or repeated
+    #[arg(short = 'J', long, value_name = "jump_host", value_delimiter = ',')]
+    pub jump: Vec<String>,
+
+    /// Optional argument
+    /// Command whose stdin/stdout is used as the transport (%h, %p, %r are expanded)
+    #[arg(long, value_name = "proxy_command")]
+    pub proxy_command: Option<String>,
+
+    /// Optional argument
+    /// Only show filesystems of these types or mounted at these paths (/path, * wildcards)
+    #[arg(long, value_name = "type_or_path", value_delimiter = ',')]
+    pub fs_include: Vec<String>,
+
+    /// Optional argument
+    /// Hide filesystems of these types or mounted at these paths (/path, * wildcards)
+    #[arg(long, value_name = "type_or_path", value_delimiter = ',')]
+    pub fs_exclude: Vec<String>,
+
+    /// Optional argument
+    /// How to read samples: exec runs a remote shell, sftp reads /proc over SFTP only
+    #[arg(long, value_name = "transport", default_value = "exec")]
+    pub transport: TransportKind,
+
+    /// Optional argument
+    /// Include partitions and loop, ram, zram, optical and device mapper devices in the disk I/O view
+    #[arg(long)]
+    pub all_disks: bool,
 }
 
-pub fn validate_parameters(cli: &Cli) -> SshConnection {
-    let parsed_host = cli.hostname.split('@').collect::<Vec<_>>();
-    if parsed_host.len() != 2 {
-        panic!("Please provide proper host! user@host:port");
+impl Cli {
+    /// Seconds between samples, only a subcommand can leave it unset.
+    pub fn interval(&self) -> usize {
+        self.interval.unwrap_or(1)
     }
-    let parsed_ip_port = parsed_host[1].split(':').collect::<Vec<_>>();
-    if parsed_ip_port.len() != 2 {
-        panic!("Please provide proper ip and port! user@host:port");
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Play back a file written with --record
+    Replay {
+        /// The recording to play
+        file: String,
+
+        /// Playback speed, 2 plays twice as fast as recorded
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+
+        /// Print each sample as plain text instead of the full-screen interface
+        #[arg(long)]
+        plain: bool,
+    },
+}
+
+/// Builds one connection per `--hostname` and per inventory entry, in that order.
+pub fn validate_parameters(cli: &Cli) -> Result<Vec<SshConnection>, Box<dyn Error>> {
+    let mut connections = Vec::new();
+    if cli.local {
+        let mut connection = SshConnection::local(cli.interval());
+        apply_options(&mut connection, cli);
+        connections.push(connection);
     }
-    if parsed_ip_port[1].parse::<usize>().is_err() {
-        panic!("Please provide proper port!");
+    for hostname in &cli.hostname {
+        connections.push(build_connection(
+            hostname,
+            cli.password.as_deref(),
+            cli.private_key_file.as_deref(),
+            &cli.jump,
+            cli,
+        )?);
     }
-    let parsed_ip = parsed_ip_port[0].split('.').collect::<Vec<_>>();
-    if parsed_ip.len() != 4 {
-        panic!("Please provide proper ip! x.x.x.x");
+
+    if let Some(path) = cli.inventory.as_deref() {
+        for host in inventory::load(path)?.host {
+            let private_key_file = host
+                .private_key_file
+                .as_deref()
+                .map(sshconfig::expand_tilde);
+            let jump = if host.jump.is_empty() {
+                &cli.jump
+            } else {
+                &host.jump
+            };
+            let mut connection = build_connection(
+                &host.hostname,
+                host.password.as_deref(),
+                private_key_file.as_deref(),
+                jump,
+                cli,
+            )
+            .map_err(|e| format!("{}: {}", host.hostname, e))?;
+            if let Some(name) = host.name {
+                connection.name = name;
+            }
+            if let Some(transport) = host.transport {
+                connection.transport = transport;
+            }
+            connections.push(connection);
+        }
     }
-    let proper_digit_count = parsed_ip
-        .iter()
-        .filter(|digit| digit.parse::<usize>().is_ok())
-        .count();
 
-    if proper_digit_count != 4 {
-        panic!("Please provide proper ip with integers! ex: 127.0.0.1");
+    if connections.is_empty() {
+        return Err(
+            "Please provide --hostname, --local or an --inventory with at least one host".into(),
+        );
     }
+    Ok(connections)
+}
 
-    let user = parsed_host[0];
-    let hostname = parsed_host[1];
-    let interval = cli.interval;
+fn build_connection(
+    destination: &str,
+    password: Option<&str>,
+    private_key_file: Option<&str>,
+    jump: &[String],
+    cli: &Cli,
+) -> Result<SshConnection, Box<dyn Error>> {
+    // A bare `localhost` is this machine, as with --local; with a user or port
+    // it is still reached through sshd.
+    if destination == "localhost" {
+        let mut connection = SshConnection::local(cli.interval());
+        apply_options(&mut connection, cli);
+        return Ok(connection);
+    }
+    let (user, hostname, port, config) = resolve(destination, cli.ssh_config.as_deref())?;
+    let interval = cli.interval();
 
-    if let Some(private_key_path) = cli.private_key_file.as_deref() {
-        return SshConnection::new(
-            user,
-            hostname,
+    let mut connection = if let Some(private_key_path) = private_key_file {
+        SshConnection::new(
+            &user,
+            &hostname,
+            port,
             None,
             Some(private_key_path),
             interval,
             ConnectionType::PrivateKey,
-        );
-    } else if let Some(password) = cli.password.as_deref() {
-        return SshConnection::new(
-            user,
-            hostname,
+        )
+    } else if let Some(password) = password {
+        SshConnection::new(
+            &user,
+            &hostname,
+            port,
             Some(password),
             None,
             interval,
             ConnectionType::Password,
-        );
+        )
+    } else {
+        SshConnection::new(
+            &user,
+            &hostname,
+            port,
+            None,
+            None,
+            interval,
+            ConnectionType::Agent,
+        )
+    };
+    connection.name = destination.to_string();
+    connection.identity_files = config.identity_files;
+    connection.identities_only = config.identities_only;
+
+    let jumps = if !jump.is_empty() {
+        jump.to_vec()
+    } else if let Some(proxy_jump) = config.proxy_jump {
+        proxy_jump.split(',').map(str::to_string).collect()
     } else {
-        return SshConnection::new(user, hostname, None, None, interval, ConnectionType::Agent);
+        Vec::new()
+    };
+    connection.jump_hosts = jump_connections(&jumps, cli, 0)?;
+    connection.proxy_command = cli.proxy_command.clone().or(config.proxy_command);
+    apply_options(&mut connection, cli);
+
+    Ok(connection)
+}
+
+/// The connections to `jumps`, in the order they are passed through. As with
+/// OpenSSH, the first one is reached the way its own ssh config says, which may
+/// put more jump hosts or a ProxyCommand in front of it.
+fn jump_connections(
+    jumps: &[String],
+    cli: &Cli,
+    depth: usize,
+) -> Result<Vec<SshConnection>, Box<dyn Error>> {
+    let mut connections = Vec::new();
+    for (index, jump) in jumps.iter().enumerate() {
+        let jump = jump.trim_start_matches("ssh://");
+        let (user, hostname, port, config) = resolve(jump, cli.ssh_config.as_deref())
+            .map_err(|e| format!("jump host {}: {}", jump, e))?;
+        // Jump hosts authenticate with the agent or their IdentityFile entries,
+        // the password and key given on the command line are for the target only.
+        let mut jump_connection = SshConnection::new(
+            &user,
+            &hostname,
+            port,
+            None,
+            None,
+            cli.interval(),
+            ConnectionType::Agent,
+        );
+        jump_connection.identity_files = config.identity_files;
+        jump_connection.identities_only = config.identities_only;
+        if index == 0 {
+            if let Some(proxy_jump) = config.proxy_jump {
+                if depth >= MAX_JUMP_DEPTH {
+                    return Err(
+                        format!("jump host {}: too many nested ProxyJump hosts", jump).into(),
+                    );
+                }
+                let hops = proxy_jump
+                    .split(',')
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                connections = jump_connections(&hops, cli, depth + 1)?;
+            }
+            jump_connection.proxy_command = config.proxy_command;
+        }
+        connections.push(jump_connection);
+    }
+    Ok(connections)
+}
+
+/// Options that apply to every monitored host.
+fn apply_options(connection: &mut SshConnection, cli: &Cli) {
+    connection.fs_filter = FsFilter {
+        include: cli.fs_include.clone(),
+        exclude: cli.fs_exclude.clone(),
+    };
+    connection.all_disks = cli.all_disks;
+    connection.transport = cli.transport;
+}
+
+/// Parses `destination` and fills in the gaps from ssh config.
+/// Returns the user, host, port and the matching config entry.
+fn resolve(
+    destination: &str,
+    ssh_config: Option<&str>,
+) -> Result<(String, String, u16, HostConfig), Box<dyn Error>> {
+    let target = parse_target(destination)?;
+    let config = sshconfig::lookup(&target.host, ssh_config)
+        .map_err(|e| format!("Failed to read ssh config: {}", e))?;
+
+    let hostname = config.hostname.clone().unwrap_or(target.host);
+    let port = target.port.or(config.port).unwrap_or(22);
+    let user = match target.user.or(config.user.clone()) {
+        Some(user) => user,
+        None => env::var("USER").map_err(|_| "No user given and $USER is not set")?,
+    };
+    Ok((user, hostname, port, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn connection(config: &str, args: &[&str]) -> Result<SshConnection, Box<dyn Error>> {
+        let path = env::temp_dir().join(format!(
+            "rsshtop-cli-{}-{}",
+            std::process::id(),
+            args.join("-")
+        ));
+        fs::write(&path, config).unwrap();
+        let mut argv = vec!["rsshtop", "-i", "1", "-F", path.to_str().unwrap()];
+        argv.extend(args);
+        let result = validate_parameters(&Cli::parse_from(argv));
+        fs::remove_file(&path).unwrap();
+        result.map(|mut connections| connections.remove(0))
+    }
+
+    fn hops(connection: &SshConnection) -> Vec<&str> {
+        connection
+            .jump_hosts
+            .iter()
+            .map(|jump| jump.hostname.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_first_jump_hosts_proxy_jump() {
+        let config = "\
+Host target
+  ProxyJump inner
+Host inner
+  ProxyJump outer,middle
+Host outer
+  ProxyJump edge
+Host middle
+  ProxyJump ignored
+  ProxyCommand ignored
+Host edge
+  ProxyCommand nc -X connect -x proxy:3128 %h %p
+Host *
+  User admin
+";
+        let target = connection(config, &["--hostname", "admin@target"]).unwrap();
+        assert_eq!(hops(&target), ["edge", "outer", "middle", "inner"]);
+        assert_eq!(
+            target.jump_hosts[0].proxy_command.as_deref(),
+            Some("nc -X connect -x proxy:3128 %h %p")
+        );
+        assert!(target.jump_hosts[2].proxy_command.is_none());
+
+        // -J replaces the target's ProxyJump, the first jump host's own is still followed.
+        let target = connection(config, &["--hostname", "admin@target", "-J", "outer"]).unwrap();
+        assert_eq!(hops(&target), ["edge", "outer"]);
+    }
+
+    #[test]
+    fn bare_localhost_is_local() {
+        let config = "Host *\n  User admin\n";
+        let local = connection(config, &["--hostname", "localhost", "--all-disks"]).unwrap();
+        assert!(local.local);
+        assert_eq!(local.name, "localhost");
+        assert!(local.all_disks);
+
+        for destination in ["admin@localhost", "localhost:2222", "127.0.0.1"] {
+            let remote = connection(config, &["--hostname", destination]).unwrap();
+            assert!(!remote.local, "{}", destination);
+            assert_eq!(remote.name, destination);
+        }
+    }
+
+    #[test]
+    fn rejects_proxy_jump_loops() {
+        let config = "Host a\n  ProxyJump b\nHost b\n  ProxyJump a\nHost *\n  User admin\n";
+        let Err(error) = connection(config, &["--hostname", "admin@target", "-J", "a"]) else {
+            panic!("a ProxyJump loop was accepted");
+        };
+        assert!(error
+            .to_string()
+            .contains("too many nested ProxyJump hosts"));
     }
 }
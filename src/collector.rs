@@ -0,0 +1,507 @@
+use crate::batch;
+use crate::disks;
+use crate::error::{MonitorError, SectionError};
+use crate::filesystems;
+use crate::processes;
+use crate::stats::Stats;
+use crate::transport::Transport;
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// One part of a sample: a shell command whose output updates `Stats`.
+pub trait Collector: Send {
+    /// Names the collector's section of the sample script and its errors.
+    fn name(&self) -> &str;
+
+    /// Shell command producing the input of `parse`.
+    fn command(&self) -> &str;
+
+    /// The same input from file reads alone, for transports that can't run
+    /// commands. Unavailable unless implemented.
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        let _ = transport;
+        Err(MonitorError::Channel(format!(
+            "{} needs a command to be run",
+            self.name()
+        )))
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError>;
+}
+
+/// Everything rsshtop shows. Interfaces come before /proc/net/dev, which only
+/// fills in the interfaces found.
+pub fn default_collectors() -> Vec<Box<dyn Collector>> {
+    vec![
+        Box::new(Uptime),
+        Box::new(Hostname),
+        Box::new(Load),
+        Box::new(Memory),
+        Box::new(FileSystems),
+        Box::new(Disks),
+        Box::new(Interfaces),
+        Box::new(NetDev),
+        Box::new(Cpu),
+        Box::new(Processes),
+    ]
+}
+
+/// Updates `stats` from every collector, in order. Where the transport runs
+/// commands, the collectors' commands are joined into one script, so a sample
+/// is one round trip. Collectors that fail are left out and listed in
+/// `stats.failed`; only a failure of the transport itself fails the sample.
+pub fn collect(
+    collectors: &mut [Box<dyn Collector>],
+    transport: &mut dyn Transport,
+    stats: &mut Stats,
+) -> Result<(), MonitorError> {
+    let mut sections = if transport.runs_commands() {
+        let commands = collectors
+            .iter()
+            .map(|collector| (collector.name(), collector.command()))
+            .collect::<Vec<_>>();
+        let names = commands.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        Some(batch::split(
+            &transport.run(&batch::script(&commands))?,
+            &names,
+        ))
+    } else {
+        None
+    };
+
+    stats.failed.clear();
+    for collector in collectors.iter_mut() {
+        let output = match &mut sections {
+            Some(sections) => match sections.remove(collector.name()) {
+                None => Err(MonitorError::Command {
+                    command: collector.command().to_string(),
+                    status: None,
+                    stderr: String::new(),
+                }),
+                // Commands like df exit non-zero when only some mounts are unreadable.
+                Some(section) if section.status != Some(0) && section.output.trim().is_empty() => {
+                    Err(MonitorError::Command {
+                        command: collector.command().to_string(),
+                        status: section.status,
+                        stderr: section.stderr,
+                    })
+                }
+                Some(section) => Ok(section.output),
+            },
+            None => collector.read(transport),
+        };
+        if let Err(error) = output.and_then(|output| collector.parse(&output, stats)) {
+            stats.failed.push(SectionError {
+                section: collector.name().to_string(),
+                error,
+            });
+        }
+    }
+    Ok(())
+}
+
+pub struct Uptime;
+
+impl Collector for Uptime {
+    fn name(&self) -> &str {
+        "uptime"
+    }
+
+    fn command(&self) -> &str {
+        "cat /proc/uptime"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/uptime")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_uptime(output)
+    }
+}
+
+pub struct Hostname;
+
+impl Collector for Hostname {
+    fn name(&self) -> &str {
+        "hostname"
+    }
+
+    fn command(&self) -> &str {
+        "hostname -f 2>/dev/null || hostname"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/sys/kernel/hostname")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_hostname(output)
+    }
+}
+
+pub struct Load;
+
+impl Collector for Load {
+    fn name(&self) -> &str {
+        "loadavg"
+    }
+
+    fn command(&self) -> &str {
+        "cat /proc/loadavg"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/loadavg")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_load(output)
+    }
+}
+
+pub struct Memory;
+
+impl Collector for Memory {
+    fn name(&self) -> &str {
+        "meminfo"
+    }
+
+    fn command(&self) -> &str {
+        "cat /proc/meminfo"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/meminfo")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_mem_info(output)
+    }
+}
+
+pub struct FileSystems;
+
+impl Collector for FileSystems {
+    fn name(&self) -> &str {
+        "filesystems"
+    }
+
+    fn command(&self) -> &str {
+        filesystems::FS_COMMAND
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        filesystems::read_files(transport)
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_fs_info(output)
+    }
+}
+
+pub struct Disks;
+
+impl Collector for Disks {
+    fn name(&self) -> &str {
+        "disks"
+    }
+
+    fn command(&self) -> &str {
+        disks::DISK_COMMAND
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        disks::read_files(transport)
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_disks(output)
+    }
+}
+
+/// Interface addresses. Without commands they are taken from /proc/net.
+pub struct Interfaces;
+
+impl Collector for Interfaces {
+    fn name(&self) -> &str {
+        "interfaces"
+    }
+
+    fn command(&self) -> &str {
+        "/bin/ip -o addr 2>/dev/null || /sbin/ip -o addr"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        let route = transport.read_file("/proc/net/route").unwrap_or_default();
+        let fib_trie = transport
+            .read_file("/proc/net/fib_trie")
+            .unwrap_or_default();
+        let if_inet6 = transport
+            .read_file("/proc/net/if_inet6")
+            .unwrap_or_default();
+        if fib_trie.is_empty() && if_inet6.is_empty() {
+            return Err(MonitorError::Read {
+                path: "/proc/net/fib_trie".to_string(),
+                message: "neither it nor /proc/net/if_inet6 is readable".to_string(),
+            });
+        }
+        Ok(ip_addr_output(&route, &fib_trie, &if_inet6))
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_interfaces(output)
+    }
+}
+
+/// Traffic counters of the interfaces found by `Interfaces`.
+pub struct NetDev;
+
+impl Collector for NetDev {
+    fn name(&self) -> &str {
+        "net/dev"
+    }
+
+    fn command(&self) -> &str {
+        "cat /proc/net/dev"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/net/dev")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_interface_info(output)
+    }
+}
+
+pub struct Cpu;
+
+impl Collector for Cpu {
+    fn name(&self) -> &str {
+        "stat"
+    }
+
+    fn command(&self) -> &str {
+        "cat /proc/stat"
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        transport.read_file("/proc/stat")
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_proc_stat(output)
+    }
+}
+
+pub struct Processes;
+
+impl Collector for Processes {
+    fn name(&self) -> &str {
+        "processes"
+    }
+
+    fn command(&self) -> &str {
+        processes::PROCESS_COMMAND
+    }
+
+    fn read(&self, transport: &mut dyn Transport) -> Result<String, MonitorError> {
+        processes::read_files(transport)
+    }
+
+    fn parse(&mut self, output: &str, stats: &mut Stats) -> Result<(), MonitorError> {
+        stats.parse_processes(output)
+    }
+}
+
+/// `ip -o addr` lines (index, interface, family, address/prefix) from the
+/// kernel's tables. The IPv4 addresses of the host are the `/32 host LOCAL`
+/// entries of fib_trie; their interface and prefix come from the most specific
+/// route containing them.
+fn ip_addr_output(route: &str, fib_trie: &str, if_inet6: &str) -> String {
+    let mut addresses = Vec::new();
+    let mut previous = "";
+    for line in fib_trie.lines() {
+        let line = line.trim();
+        let address = previous.trim_start_matches(['|', '-', ' ']);
+        previous = line;
+        if line != "/32 host LOCAL" {
+            continue;
+        }
+        if let Ok(ip) = address.parse::<Ipv4Addr>() {
+            if !addresses.contains(&ip) {
+                addresses.push(ip);
+            }
+        }
+    }
+
+    // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+    let mut routes = route
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let hex = |field: &str| u32::from_str_radix(field, 16).ok();
+            Some((
+                fields.first()?.to_string(),
+                hex(fields.get(1)?)?,
+                hex(fields.get(7)?)?,
+            ))
+        })
+        .collect::<Vec<_>>();
+    if route_is_little_endian(&routes, &addresses) {
+        for (_, destination, mask) in &mut routes {
+            *destination = destination.swap_bytes();
+            *mask = mask.swap_bytes();
+        }
+    }
+
+    let mut output = String::new();
+    for ip in addresses {
+        let bits = u32::from(ip);
+        // The default route's mask of 0 would match any address.
+        let route = routes
+            .iter()
+            .filter(|(_, destination, mask)| *mask != 0 && bits & mask == *destination)
+            .max_by_key(|(_, _, mask)| mask.count_ones());
+        let (interface, prefix) = match route {
+            _ if ip.is_loopback() => ("lo", 8),
+            Some((interface, _, mask)) => (interface.as_str(), mask.count_ones()),
+            None => continue,
+        };
+        let _ = writeln!(output, "0: {} inet {}/{}", interface, ip, prefix);
+    }
+
+    // address index prefix-length scope flags interface, all in hex
+    for line in if_inet6.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 6 {
+            continue;
+        }
+        let (Ok(address), Ok(prefix)) = (
+            u128::from_str_radix(fields[0], 16),
+            u8::from_str_radix(fields[2], 16),
+        ) else {
+            continue;
+        };
+        let _ = writeln!(
+            output,
+            "0: {} inet6 {}/{}",
+            fields[5],
+            Ipv6Addr::from(address),
+            prefix
+        );
+    }
+    output
+}
+
+/// Whether the route table, which holds addresses as the host's byte order
+/// prints them in hex, comes from a little-endian host. Only in the right order
+/// is every netmask a run of leading ones. When the masks don't tell (all /0 or
+/// /32), the order that puts more of the host's addresses inside its routes wins,
+/// little-endian on a tie as the far more common case.
+fn route_is_little_endian(routes: &[(String, u32, u32)], addresses: &[Ipv4Addr]) -> bool {
+    let is_prefix = |mask: u32| mask.leading_ones() == mask.count_ones();
+    let (little, big) = routes.iter().fold((0, 0), |(little, big), (_, _, mask)| {
+        (
+            little + usize::from(is_prefix(mask.swap_bytes())),
+            big + usize::from(is_prefix(*mask)),
+        )
+    });
+    if little != big {
+        return little > big;
+    }
+    let contained = |swap: bool| {
+        addresses
+            .iter()
+            .filter(|ip| {
+                routes.iter().any(|(_, destination, mask)| {
+                    let (destination, mask) = match swap {
+                        true => (destination.swap_bytes(), mask.swap_bytes()),
+                        false => (*destination, *mask),
+                    };
+                    mask != 0 && u32::from(**ip) & mask == destination
+                })
+            })
+            .count()
+    };
+    contained(true) >= contained(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIB_TRIE: &str = "\
+Local:
+  +-- 0.0.0.0/0 3 0 5
+     +-- 127.0.0.0/8 2 0 2
+        +-- 127.0.0.0/31 1 0 0
+           |-- 127.0.0.1
+              /32 host LOCAL
+     +-- 192.168.1.0/24 2 0 2
+        +-- 192.168.1.0/28 2 0 2
+           |-- 192.168.1.0
+              /24 link UNICAST
+           |-- 192.168.1.10
+              /32 host LOCAL
+     +-- 10.8.0.0/16 2 0 2
+           |-- 10.8.3.4
+              /32 host LOCAL
+";
+    const HEADER: &str =
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n";
+
+    #[test]
+    fn reads_little_endian_routes() {
+        let route = format!(
+            "{}{}{}{}",
+            HEADER,
+            "ens3\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n",
+            "ens3\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n",
+            "tun0\t0000080A\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0\n",
+        );
+        assert_eq!(
+            ip_addr_output(&route, FIB_TRIE, ""),
+            "0: lo inet 127.0.0.1/8\n0: ens3 inet 192.168.1.10/24\n0: tun0 inet 10.8.3.4/16\n"
+        );
+    }
+
+    #[test]
+    fn reads_big_endian_routes() {
+        let route = format!(
+            "{}{}{}{}",
+            HEADER,
+            "eth0\t00000000\tC0A80101\t0003\t0\t0\t100\t00000000\t0\t0\t0\n",
+            "eth0\tC0A80100\t00000000\t0001\t0\t0\t100\tFFFFFF00\t0\t0\t0\n",
+            "tun0\t0A080000\t00000000\t0001\t0\t0\t0\tFFFF0000\t0\t0\t0\n",
+        );
+        assert_eq!(
+            ip_addr_output(&route, FIB_TRIE, ""),
+            "0: lo inet 127.0.0.1/8\n0: eth0 inet 192.168.1.10/24\n0: tun0 inet 10.8.3.4/16\n"
+        );
+    }
+
+    #[test]
+    fn host_routes_are_placed_by_the_local_addresses() {
+        // Only /0 and /32 masks, which read the same in either byte order.
+        let fib_trie = "Local:\n  |-- 10.8.3.4\n     /32 host LOCAL\n";
+        let big = format!(
+            "{}{}",
+            HEADER, "ppp0\t0A080304\t00000000\t0005\t0\t0\t0\tFFFFFFFF\t0\t0\t0\n"
+        );
+        assert_eq!(
+            ip_addr_output(&big, fib_trie, ""),
+            "0: ppp0 inet 10.8.3.4/32\n"
+        );
+        let little = format!(
+            "{}{}",
+            HEADER, "ppp0\t0403080A\t00000000\t0005\t0\t0\t0\tFFFFFFFF\t0\t0\t0\n"
+        );
+        assert_eq!(
+            ip_addr_output(&little, fib_trie, ""),
+            "0: ppp0 inet 10.8.3.4/32\n"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::error::MonitorError;
+use crate::transport::Transport;
+use std::collections::HashMap;
+
+/// Uptime to time the sample, the whole disks (partitions have no entry in
+/// /sys/block, where a `/` in a device name is written as `!`) and the I/O
+/// counters of every block device.
+pub const DISK_COMMAND: &str = "cat /proc/uptime; \
+    echo '@@block'; ls /sys/block 2>/dev/null; \
+    echo '@@diskstats'; cat /proc/diskstats";
+
+/// /proc/diskstats counts in 512-byte sectors whatever the device's sector size.
+const SECTOR_SIZE: f64 = 512.0;
+
+#[derive(Clone)]
+pub struct DiskInfo {
+    pub name: String,
+    /// A partition of a disk that is listed itself.
+    pub partition: bool,
+    /// Bytes per second.
+    pub read_rate: f64,
+    pub write_rate: f64,
+    /// Completed requests per second.
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Average time a request took, queueing included, in milliseconds.
+    pub await_ms: f64,
+    /// Share of the interval the device was busy, in percent.
+    pub util: f32,
+}
+
+/// Devices hidden unless asked for: loop devices, ram disks and compressed swap
+/// in memory, optical drives, and device mapper targets, whose I/O is also
+/// counted on the disks below them.
+const HIDDEN_PREFIXES: &[&str] = &["loop", "ram", "zram", "sr", "dm-"];
+
+impl DiskInfo {
+    /// Whole disks other than loop, ram, zram, optical and device mapper devices.
+    pub fn shown_by_default(&self) -> bool {
+        !self.partition
+            && !HIDDEN_PREFIXES
+                .iter()
+                .any(|prefix| self.name.starts_with(prefix))
+    }
+}
+
+/// Raw counters per device and the uptime they were read at, for the next sample's rates.
+#[derive(Default, Clone)]
+pub struct DiskCounters {
+    pub uptime: f64,
+    pub devices: HashMap<String, DiskRaw>,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct DiskRaw {
+    reads: u64,
+    read_sectors: u64,
+    read_ms: u64,
+    writes: u64,
+    write_sectors: u64,
+    write_ms: u64,
+    io_ms: u64,
+}
+
+/// Parses the output of `DISK_COMMAND`, sorted by device name. Rates are computed
+/// against `prev` and stay 0 for devices that were not present in it or whose
+/// counters went backwards.
+pub fn parse(output: &str, prev: &DiskCounters) -> (Vec<DiskInfo>, DiskCounters) {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut section = "uptime";
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("@@") {
+            section = name;
+            continue;
+        }
+        sections.entry(section).or_default().push(line);
+    }
+    let section = |name: &str| sections.get(name).cloned().unwrap_or_default();
+
+    let uptime = section("uptime")
+        .first()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|uptime| uptime.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let elapsed = uptime - prev.uptime;
+    let block = section("block")
+        .iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| name.replace('!', "/"))
+        .collect::<Vec<_>>();
+
+    let mut devices = HashMap::new();
+    let mut disks = Vec::new();
+    for line in section("diskstats") {
+        // major minor name reads merged sectors ms writes merged sectors ms in-flight io_ms ...
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 13 {
+            continue;
+        }
+        let counter = |index: usize| fields[index].parse::<u64>().unwrap_or(0);
+        let raw = DiskRaw {
+            reads: counter(3),
+            read_sectors: counter(5),
+            read_ms: counter(6),
+            writes: counter(7),
+            write_sectors: counter(9),
+            write_ms: counter(10),
+            io_ms: counter(12),
+        };
+        let name = fields[2].to_string();
+
+        let mut disk = DiskInfo {
+            name: name.clone(),
+            partition: !block.is_empty() && !block.contains(&name),
+            read_rate: 0.0,
+            write_rate: 0.0,
+            read_iops: 0.0,
+            write_iops: 0.0,
+            await_ms: 0.0,
+            util: 0.0,
+        };
+        if let Some(prev_raw) = prev.devices.get(&name) {
+            if elapsed > 0.0 {
+                fill_rates(&mut disk, prev_raw, &raw, elapsed);
+            }
+        }
+        devices.insert(name, raw);
+        disks.push(disk);
+    }
+    disks.sort_by(|a, b| a.name.cmp(&b.name));
+
+    (disks, DiskCounters { uptime, devices })
+}
+
+fn fill_rates(disk: &mut DiskInfo, prev: &DiskRaw, current: &DiskRaw, elapsed: f64) {
+    let delta = |prev: u64, current: u64| current.checked_sub(prev);
+    let (
+        Some(reads),
+        Some(read_sectors),
+        Some(read_ms),
+        Some(writes),
+        Some(write_sectors),
+        Some(write_ms),
+        Some(io_ms),
+    ) = (
+        delta(prev.reads, current.reads),
+        delta(prev.read_sectors, current.read_sectors),
+        delta(prev.read_ms, current.read_ms),
+        delta(prev.writes, current.writes),
+        delta(prev.write_sectors, current.write_sectors),
+        delta(prev.write_ms, current.write_ms),
+        delta(prev.io_ms, current.io_ms),
+    )
+    else {
+        return;
+    };
+
+    disk.read_rate = read_sectors as f64 * SECTOR_SIZE / elapsed;
+    disk.write_rate = write_sectors as f64 * SECTOR_SIZE / elapsed;
+    disk.read_iops = reads as f64 / elapsed;
+    disk.write_iops = writes as f64 / elapsed;
+    if reads + writes > 0 {
+        disk.await_ms = (read_ms + write_ms) as f64 / (reads + writes) as f64;
+    }
+    disk.util = (io_ms as f64 / (elapsed * 1000.0) * 100.0).min(100.0) as f32;
+}
+
+/// Output in the format of `DISK_COMMAND` from file reads alone.
+pub fn read_files(transport: &mut dyn Transport) -> Result<String, MonitorError> {
+    let uptime = transport.read_file("/proc/uptime")?;
+    let block = transport.list_dir("/sys/block").unwrap_or_default();
+    let diskstats = transport.read_file("/proc/diskstats")?;
+    Ok(format!(
+        "{}@@block\n{}\n@@diskstats\n{}",
+        uptime,
+        block.join("\n"),
+        diskstats
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISKSTATS: &str = "\
+   8       0 sda 100 0 2000 50 200 0 4000 150 0 120 200 0 0 0 0
+   8       1 sda1 90 0 1800 40 180 0 3600 140 0 110 180 0 0 0 0
+ 104       0 cciss/c0d0 10 0 80 5 20 0 160 15 0 12 20 0 0 0 0
+ 104       1 cciss/c0d0p1 9 0 72 4 18 0 144 14 0 11 18 0 0 0 0
+   7       0 loop0 1 0 8 0 0 0 0 0 0 1 0 0 0 0 0
+   1       0 ram0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0
+ 252       0 zram0 40 0 320 0 80 0 640 1 0 2 1 0 0 0 0
+  11       0 sr0 3 0 24 1 0 0 0 0 0 1 1 0 0 0 0
+ 253       0 dm-0 95 0 1900 45 190 0 3800 145 0 115 190 0 0 0 0
+";
+
+    fn sample(uptime: &str, block: &str) -> String {
+        format!(
+            "{} 0.00\n@@block\n{}\n@@diskstats\n{}",
+            uptime, block, DISKSTATS
+        )
+    }
+
+    fn partitions(disks: &[DiskInfo]) -> Vec<(&str, bool)> {
+        disks
+            .iter()
+            .map(|disk| (disk.name.as_str(), disk.partition))
+            .collect()
+    }
+
+    #[test]
+    fn tells_partitions_from_disks() {
+        let (disks, _) = parse(
+            &sample("100.00", "cciss!c0d0\ndm-0\nloop0\nram0\nsda\nsr0\nzram0"),
+            &DiskCounters::default(),
+        );
+        assert_eq!(
+            partitions(&disks),
+            [
+                ("cciss/c0d0", false),
+                ("cciss/c0d0p1", true),
+                ("dm-0", false),
+                ("loop0", false),
+                ("ram0", false),
+                ("sda", false),
+                ("sda1", true),
+                ("sr0", false),
+                ("zram0", false),
+            ]
+        );
+        let shown = disks
+            .iter()
+            .filter(|disk| disk.shown_by_default())
+            .map(|disk| disk.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(shown, ["cciss/c0d0", "sda"]);
+    }
+
+    #[test]
+    fn everything_is_a_disk_without_sys_block() {
+        let (disks, _) = parse(&sample("100.00", ""), &DiskCounters::default());
+        assert!(disks.iter().all(|disk| !disk.partition));
+    }
+
+    #[test]
+    fn rates_from_previous_counters() {
+        let (_, prev) = parse(&sample("100.00", "sda"), &DiskCounters::default());
+        let mut prev = prev;
+        let sda = prev.devices.get_mut("sda").unwrap();
+        sda.reads -= 50;
+        sda.read_sectors -= 1000;
+        sda.read_ms -= 25;
+        sda.io_ms -= 100;
+        prev.uptime = 98.0;
+
+        let (disks, _) = parse(&sample("100.00", "sda"), &prev);
+        let sda = disks.iter().find(|disk| disk.name == "sda").unwrap();
+        assert_eq!(sda.read_rate, 1000.0 * 512.0 / 2.0);
+        assert_eq!(sda.read_iops, 25.0);
+        assert_eq!(sda.await_ms, 0.5);
+        assert_eq!(sda.util, 5.0);
+        assert_eq!(sda.write_rate, 0.0);
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// Everything that can go wrong reaching a host or reading a sample from it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorError {
+    /// The host could not be reached, or the SSH handshake failed.
+    Connect(String),
+    /// The host key is unknown and refused, or has changed.
+    HostKey(String),
+    Auth(String),
+    /// Opening or using a channel, the remote shell or the SFTP subsystem failed.
+    Channel(String),
+    /// A collector's command failed without output. `status` is `None` when
+    /// the output ended before the exit status.
+    Command {
+        command: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+    Read {
+        path: String,
+        message: String,
+    },
+    /// Output not in the expected format, at a 1-based line of a file (or of
+    /// the command output standing in for it).
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    /// An error read back from a recording, in the words it was recorded with.
+    Recorded(String),
+}
+
+impl MonitorError {
+    pub fn parse(file: &str, line: usize, message: impl Display) -> Self {
+        MonitorError::Parse {
+            file: file.to_string(),
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonitorError::Connect(e) => write!(f, "Failed to connect: {}", e),
+            MonitorError::HostKey(e) => write!(f, "Host key verification failed: {}", e),
+            MonitorError::Auth(e) => write!(f, "Authentication failed: {}", e),
+            MonitorError::Channel(e) => write!(f, "Channel error: {}", e),
+            MonitorError::Command { status, stderr, .. } => {
+                match status {
+                    Some(status) => write!(f, "command exited with status {}", status)?,
+                    None => write!(f, "command output ended without an exit status")?,
+                }
+                // The last line usually says why, after any warnings.
+                match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+                    Some(line) => write!(f, ": {}", line.trim()),
+                    None => Ok(()),
+                }
+            }
+            MonitorError::Read { path, message } => {
+                write!(f, "Failed to read {}: {}", path, message)
+            }
+            MonitorError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{} line {}: {}", file, line, message),
+            MonitorError::Recorded(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MonitorError {}
+
+/// A collector that failed in a sample whose other collectors succeeded.
+#[derive(Debug, Clone)]
+pub struct SectionError {
+    /// The collector's name.
+    pub section: String,
+    pub error: MonitorError,
+}
+
+impl Display for SectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.section, self.error)
+    }
+}
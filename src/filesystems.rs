@@ -0,0 +1,339 @@
+use crate::error::MonitorError;
+use crate::sshconfig::wildcard_match;
+use crate::transport::{FsUsage, Transport};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Sizes of every mounted filesystem in bytes, then their inode counts.
+/// `df` leaves out pseudo filesystems like proc and sysfs unless given `-a`.
+/// A stale network mount can hang it, so where `timeout` works it gets 5
+/// seconds, and if that's not enough to print anything only the local
+/// filesystems (`-l`) are listed.
+pub const FS_COMMAND: &str = "if timeout 1 true 2>/dev/null; then rsshtop_t='timeout 5'; \
+    else rsshtop_t=; fi; \
+    rsshtop_df() { out=$($rsshtop_t df \"$@\" 2>/dev/null); \
+    [ -n \"$out\" ] || out=$($rsshtop_t df -l \"$@\" 2>/dev/null); printf '%s\\n' \"$out\"; }; \
+    rsshtop_df -PTB1; echo '@@inodes'; rsshtop_df -Pi";
+
+#[derive(Clone)]
+pub struct FileSystemInfo {
+    pub device: String,
+    pub fs_type: String,
+    pub mount_point: String,
+    pub size: u64,
+    pub used: u64,
+    pub free: u64,
+    /// Used share of the space available to unprivileged users, like df's Capacity.
+    pub used_percent: f32,
+    /// 0 for filesystems without a fixed inode table (btrfs, vfat, ...).
+    pub inodes: u64,
+    pub inodes_used: u64,
+}
+
+impl FileSystemInfo {
+    pub fn inodes_percent(&self) -> Option<f32> {
+        if self.inodes == 0 {
+            return None;
+        }
+        Some(self.inodes_used as f32 / self.inodes as f32 * 100.0)
+    }
+}
+
+/// Which filesystems to show. A pattern starting with `/` matches the mount point,
+/// anything else the filesystem type; both may use `*` and `?` wildcards.
+/// With no include patterns every filesystem is included.
+#[derive(Default, Clone)]
+pub struct FsFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl FsFilter {
+    pub fn matches(&self, fs: &FileSystemInfo) -> bool {
+        let matches = |pattern: &String| {
+            if pattern.starts_with('/') {
+                wildcard_match(pattern, &fs.mount_point)
+            } else {
+                wildcard_match(pattern, &fs.fs_type)
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Parses the output of `FS_COMMAND`, in `df` order.
+pub fn parse(output: &str) -> Vec<FileSystemInfo> {
+    let (sizes, inodes) = output.split_once("@@inodes").unwrap_or((output, ""));
+
+    // Filesystem Inodes IUsed IFree IUse% Mounted on
+    let mut inode_counts: HashMap<String, (u64, u64)> = HashMap::new();
+    for line in inodes.lines() {
+        if let Some((_, numbers, mount_point)) = df_columns(line, 1) {
+            inode_counts.insert(mount_point, (numbers[0], numbers[1]));
+        }
+    }
+
+    // Filesystem Type 1-blocks Used Available Capacity Mounted on
+    let mut fs_infos = Vec::new();
+    for line in sizes.lines() {
+        let Some((left, numbers, mount_point)) = df_columns(line, 2) else {
+            continue;
+        };
+        let [size, used, free] = numbers;
+        if size == 0 {
+            continue;
+        }
+        let (inodes, inodes_used) = inode_counts.get(&mount_point).copied().unwrap_or((0, 0));
+        let used_percent = if used + free == 0 {
+            0.0
+        } else {
+            used as f32 / (used + free) as f32 * 100.0
+        };
+
+        let (fs_type, device) = left.split_last().expect("df_columns leaves two fields");
+        fs_infos.push(FileSystemInfo {
+            device: device.join(" "),
+            fs_type: fs_type.to_string(),
+            mount_point,
+            size,
+            used,
+            free,
+            used_percent,
+            inodes,
+            inodes_used,
+        });
+    }
+    fs_infos
+}
+
+/// Splits a `df -P` line into the fields before its three counts (at least
+/// `leading` of them), the counts, and the mount point after the percentage.
+/// Devices and mount points may contain spaces, so the line is taken apart at
+/// the first run of three counts and a percentage rather than by position.
+fn df_columns(line: &str, leading: usize) -> Option<(Vec<&str>, [u64; 3], String)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    let is_percent = |part: &str| {
+        part == "-"
+            || part
+                .strip_suffix('%')
+                .is_some_and(|number| number.parse::<u64>().is_ok())
+    };
+    (leading + 3..parts.len().saturating_sub(1)).find_map(|percent| {
+        if !is_percent(parts[percent]) {
+            return None;
+        }
+        let number = |index: usize| parts[index].parse::<u64>().ok();
+        let numbers = [
+            number(percent - 3)?,
+            number(percent - 2)?,
+            number(percent - 1)?,
+        ];
+        Some((
+            parts[..percent - 3].to_vec(),
+            numbers,
+            parts[percent + 1..].join(" "),
+        ))
+    })
+}
+
+/// Output in the format of `FS_COMMAND` from /proc/mounts and the usage of
+/// every mount, for transports that can't run `df`.
+pub fn read_files(transport: &mut dyn Transport) -> Result<String, MonitorError> {
+    let mounts = transport.read_file("/proc/mounts")?;
+    let mut usages = Vec::new();
+    for mount in parse_mounts(&mounts) {
+        // Unreadable mounts are left out, like df does.
+        if let Ok(usage) = transport.fs_usage(&mount.mount_point) {
+            usages.push((mount, usage));
+        }
+    }
+    Ok(df_output(&usages))
+}
+
+struct Mount {
+    device: String,
+    fs_type: String,
+    mount_point: String,
+}
+
+/// Entries of /proc/mounts. Spaces and other special characters in paths are
+/// octal escapes there, like `\040`.
+fn parse_mounts(mounts: &str) -> Vec<Mount> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 3 {
+                return None;
+            }
+            Some(Mount {
+                device: unescape_octal(fields[0]),
+                fs_type: fields[2].to_string(),
+                mount_point: unescape_octal(fields[1]),
+            })
+        })
+        .collect()
+}
+
+fn unescape_octal(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok())
+        {
+            Some(byte) => {
+                result.push(byte);
+                i += 4;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// What `df -PTB1; echo @@inodes; df -Pi` prints for `usages`.
+fn df_output(usages: &[(Mount, FsUsage)]) -> String {
+    let mut sizes = String::from("Filesystem Type 1-blocks Used Available Capacity Mounted on\n");
+    let mut inodes = String::from("Filesystem Inodes IUsed IFree IUse% Mounted on\n");
+    for (mount, usage) in usages {
+        let size = usage.blocks * usage.block_size;
+        let used = usage.blocks.saturating_sub(usage.blocks_free) * usage.block_size;
+        let available = usage.blocks_available * usage.block_size;
+        let capacity = percent(used, used + available);
+        let _ = writeln!(
+            sizes,
+            "{} {} {} {} {} {}% {}",
+            mount.device, mount.fs_type, size, used, available, capacity, mount.mount_point
+        );
+        let inodes_used = usage.inodes.saturating_sub(usage.inodes_free);
+        let _ = writeln!(
+            inodes,
+            "{} {} {} {} {}% {}",
+            mount.device,
+            usage.inodes,
+            inodes_used,
+            usage.inodes_free,
+            percent(inodes_used, usage.inodes),
+            mount.mount_point
+        );
+    }
+    format!("{}@@inodes\n{}", sizes, inodes)
+}
+
+fn percent(part: u64, total: u64) -> u64 {
+    if total == 0 {
+        return 0;
+    }
+    (part * 100).div_ceil(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell::run_local;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn command_takes_df_from_the_path() {
+        assert!(!FS_COMMAND.contains("/bin/df"));
+    }
+
+    #[test]
+    fn lists_local_filesystems_when_df_hangs() {
+        let dir = env::temp_dir().join(format!("rsshtop-df-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            fs::write(&path, format!("#!/bin/sh\n{}", body)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        // A df that hangs on a stale NFS mount unless told to skip remote ones.
+        script(
+            "df",
+            "[ \"$1\" = -l ] || exec sleep 30\n\
+             case \"$2\" in\n\
+             -PTB1) echo 'Filesystem Type 1-blocks Used Available Capacity Mounted on'\n\
+             echo '/dev/sda1 ext4 1000 400 600 40% /' ;;\n\
+             -Pi) echo 'Filesystem Inodes IUsed IFree IUse% Mounted on'\n\
+             echo '/dev/sda1 100 10 90 10% /' ;;\n\
+             esac\n",
+        );
+        // And a timeout that doesn't take the 5 seconds it is given.
+        script("timeout", "shift\nexec /usr/bin/timeout 0.2 \"$@\"\n");
+
+        let started = Instant::now();
+        let output = run_local(&format!("PATH={}:$PATH\n{}", dir.display(), FS_COMMAND)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let fs_infos = parse(&output);
+        assert_eq!(fs_infos.len(), 1);
+        assert_eq!(fs_infos[0].mount_point, "/");
+        assert_eq!(fs_infos[0].inodes_used, 10);
+    }
+
+    #[test]
+    fn parses_names_with_spaces() {
+        let output = "\
+Filesystem Type 1-blocks Used Available Capacity Mounted on
+/dev/sda1 ext4 1000 400 500 45% /
+//nas/my share cifs 2000 1000 1000 50% /mnt/My Photos 2%
+tmpfs tmpfs 0 0 0 - /run/empty
+@@inodes
+Filesystem Inodes IUsed IFree IUse% Mounted on
+/dev/sda1 100 10 90 10% /
+//nas/my share 0 0 0 - /mnt/My Photos 2%
+";
+        let fs_infos = parse(output);
+        assert_eq!(fs_infos.len(), 2);
+        assert_eq!(fs_infos[0].device, "/dev/sda1");
+        assert_eq!(fs_infos[0].inodes_used, 10);
+
+        let share = &fs_infos[1];
+        assert_eq!(share.device, "//nas/my share");
+        assert_eq!(share.fs_type, "cifs");
+        assert_eq!(share.mount_point, "/mnt/My Photos 2%");
+        assert_eq!((share.size, share.used, share.free), (2000, 1000, 1000));
+        assert_eq!(share.used_percent, 50.0);
+        assert_eq!(share.inodes, 0);
+    }
+
+    #[test]
+    fn df_output_parses_back() {
+        let mounts = parse_mounts(
+            "/dev/vdb1 /srv/media\\040share ext4 rw,relatime 0 0\nmy\\040pool /mnt/tank\\0401 zfs rw 0 0\n",
+        );
+        let usage = |blocks, free, inodes, inodes_free| FsUsage {
+            block_size: 4096,
+            blocks,
+            blocks_free: free,
+            blocks_available: free,
+            inodes,
+            inodes_free,
+        };
+        let usages = mounts
+            .into_iter()
+            .zip([usage(1000, 250, 64, 16), usage(500, 500, 0, 0)])
+            .collect::<Vec<_>>();
+
+        let fs_infos = parse(&df_output(&usages));
+        assert_eq!(fs_infos.len(), 2);
+        assert_eq!(fs_infos[0].mount_point, "/srv/media share");
+        assert_eq!(fs_infos[0].used, 750 * 4096);
+        assert_eq!(fs_infos[0].inodes_used, 48);
+        assert_eq!(fs_infos[1].device, "my pool");
+        assert_eq!(fs_infos[1].fs_type, "zfs");
+        assert_eq!(fs_infos[1].mount_point, "/mnt/tank 1");
+        assert_eq!(fs_infos[1].used, 0);
+    }
+}
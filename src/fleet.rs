@@ -0,0 +1,348 @@
+use crate::sshconnect::{self, ManagedSession, Sample, SshConnection};
+use colored::{ColoredString, Colorize};
+use crossbeam_channel::{bounded, never, select, tick, unbounded, Receiver, Sender};
+use remote_monitoring::stats::{format_bytes, Stats, ESC};
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub enum HostStatus {
+    Connecting,
+    Up,
+    /// Connected, but the last sample failed.
+    Failed(String),
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+}
+
+/// Samples kept for the sparklines in the TUI.
+pub const HISTORY_LEN: usize = 300;
+
+/// What the overview knows about one host, shared between its collector thread and the renderer.
+pub struct HostState {
+    pub name: String,
+    pub status: HostStatus,
+    pub stats: Option<Stats>,
+    /// Samples collected since the session was (re)connected. The first one
+    /// only sets the baselines for CPU and rates.
+    pub samples: u64,
+    /// Alert rules currently firing.
+    pub alerts: Vec<String>,
+    /// Problems that don't fail the sample, like a recording that stopped.
+    pub warnings: Vec<String>,
+    /// Received plus transmitted bytes per second over all interfaces.
+    pub net_rate: f64,
+    /// Busy CPU percent per sample, oldest first.
+    pub cpu_history: VecDeque<u64>,
+    /// Used memory percent per sample, oldest first.
+    pub mem_history: VecDeque<u64>,
+    /// Network bytes per second per sample, oldest first.
+    pub net_history: VecDeque<u64>,
+}
+
+impl HostState {
+    pub fn new(name: &str) -> Self {
+        HostState {
+            name: name.to_string(),
+            status: HostStatus::Connecting,
+            stats: None,
+            samples: 0,
+            alerts: Vec::new(),
+            warnings: Vec::new(),
+            net_rate: 0.0,
+            cpu_history: VecDeque::with_capacity(HISTORY_LEN),
+            mem_history: VecDeque::with_capacity(HISTORY_LEN),
+            net_history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    /// Takes a successful sample: keeps `stats` and adds it to the histories.
+    pub fn collected(&mut self, stats: &Stats) {
+        self.net_rate = stats.net_rate();
+        self.record(stats);
+        self.stats = Some(stats.clone());
+        self.samples += 1;
+        self.status = HostStatus::Up;
+    }
+
+    fn record(&mut self, stats: &Stats) {
+        push_sample(&mut self.cpu_history, stats.cpu_usage().round() as u64);
+        push_sample(&mut self.mem_history, stats.mem_usage().round() as u64);
+        push_sample(&mut self.net_history, self.net_rate as u64);
+    }
+}
+
+fn push_sample(history: &mut VecDeque<u64>, value: u64) {
+    if history.len() == HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+/// Opens a session to every host concurrently. Hosts that cannot be reached
+/// now are retried with backoff like dropped sessions.
+pub fn connect_all(
+    connections: Vec<SshConnection>,
+    refuse_unknown_hosts: bool,
+) -> Vec<ManagedSession> {
+    let pending = connections
+        .into_iter()
+        .map(|connection| {
+            thread::spawn(move || {
+                if connection.local {
+                    return ManagedSession::local(connection);
+                }
+                let session = sshconnect::open_session(&connection, refuse_unknown_hosts)
+                    .map_err(|e| e.to_string());
+                ManagedSession::new(connection, session)
+            })
+        })
+        .collect::<Vec<_>>();
+    pending
+        .into_iter()
+        .map(|handle| handle.join().expect("connect thread panicked"))
+        .collect()
+}
+
+/// One collector thread per host. Each `tick` asks every collector for a new sample,
+/// and `updates` receives a message whenever a host's state changed.
+pub struct Fleet {
+    pub states: Vec<Arc<Mutex<HostState>>>,
+    pub updates: Receiver<()>,
+    workers: Vec<Sender<()>>,
+}
+
+impl Fleet {
+    pub fn start(sessions: Vec<ManagedSession>) -> Self {
+        let (update_sender, updates) = unbounded();
+        let mut states = Vec::new();
+        let mut workers = Vec::new();
+        for managed in sessions {
+            let state = Arc::new(Mutex::new(HostState::new(managed.name())));
+            let (sender, receiver) = bounded(1);
+            workers.push(sender);
+            states.push(Arc::clone(&state));
+            let update_sender = update_sender.clone();
+            thread::spawn(move || collect(managed, state, receiver, update_sender));
+        }
+        Fleet {
+            states,
+            updates,
+            workers,
+        }
+    }
+
+    pub fn tick(&self) {
+        // A host still busy with the previous sample skips this tick.
+        for worker in &self.workers {
+            let _ = worker.try_send(());
+        }
+    }
+}
+
+/// Plain-text fleet overview. Typing a row number and Enter drills into that host.
+pub fn run_plain(fleet: Fleet, interval: usize, ctrl_c_events: Receiver<()>) {
+    let mut input = stdin_lines();
+    let ticks = tick(Duration::from_secs(interval as u64));
+    let mut selected: Option<usize> = None;
+    fleet.tick();
+    render(&fleet.states, selected);
+
+    loop {
+        select! {
+            recv(ticks) -> _ => fleet.tick(),
+            recv(fleet.updates) -> _ => render(&fleet.states, selected),
+            recv(input) -> line => {
+                match line {
+                    Ok(line) => {
+                        selected = match line.trim().parse::<usize>() {
+                            Ok(row) if row >= 1 && row <= fleet.states.len() => Some(row - 1),
+                            _ => None,
+                        };
+                        render(&fleet.states, selected);
+                    }
+                    Err(_) => input = never(),
+                }
+            }
+            recv(ctrl_c_events) -> _ => {
+                println!("Goodbye!");
+                break;
+            }
+        }
+    }
+}
+
+fn collect(
+    mut managed: ManagedSession,
+    state: Arc<Mutex<HostState>>,
+    ticks: Receiver<()>,
+    updates: Sender<()>,
+) {
+    let mut stats = Stats::default();
+    for _ in ticks.iter() {
+        let sample = managed.sample(&mut stats);
+        let mut state = state.lock().unwrap();
+        match sample {
+            Sample::Collected => state.collected(&stats),
+            Sample::Failed(e) => state.status = HostStatus::Failed(e),
+            Sample::Reconnecting { attempt, error } => {
+                state.samples = 0;
+                state.status = HostStatus::Reconnecting { attempt, error };
+            }
+        }
+        state.alerts = managed.firing_alerts();
+        state.warnings = managed.warnings();
+        drop(state);
+        if updates.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+fn stdin_lines() -> Receiver<String> {
+    let (sender, receiver) = unbounded();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    });
+    receiver
+}
+
+fn render(states: &[Arc<Mutex<HostState>>], selected: Option<usize>) {
+    if let Some(index) = selected {
+        let state = states[index].lock().unwrap();
+        match (&state.status, &state.stats) {
+            (HostStatus::Up, Some(stats)) => println!("{}", stats),
+            (HostStatus::Failed(e), _) => println!("{}{}: {}\n", ESC, state.name.bold(), e.red()),
+            (HostStatus::Reconnecting { attempt, error }, stats) => {
+                match stats {
+                    Some(stats) => println!("{}", stats),
+                    None => println!("{}{}\n", ESC, state.name.bold()),
+                }
+                println!("{}\n", reconnect_status(*attempt, error));
+            }
+            _ => println!("{}{}: waiting for data\n", ESC, state.name.bold()),
+        }
+        if !state.alerts.is_empty() {
+            println!("{}\n", alert_status(&state.alerts));
+        }
+        for warning in &state.warnings {
+            println!("{}\n", warning_status(warning));
+        }
+        println!("{}", "Press Enter to return to the overview.".dimmed());
+        return;
+    }
+
+    let mut out = String::new();
+    out.push_str(ESC);
+    out.push_str(&format!(
+        "{}\n\n{:>3}  {:<24} {:>6} {:>6} {:>6}  {:<24} {:>14}\n",
+        "Fleet overview".bold().bright_green(),
+        "#",
+        "HOST",
+        "LOAD",
+        "CPU%",
+        "MEM%",
+        "FULLEST FS",
+        "NET",
+    ));
+    for (row, state) in states.iter().enumerate() {
+        let state = state.lock().unwrap();
+        let name = truncate(&state.name, 24);
+        match (&state.status, &state.stats) {
+            (HostStatus::Failed(e), _) => out.push_str(&format!(
+                "{:>3}  {:<24} {}\n",
+                row + 1,
+                name,
+                format!("error: {}", e).red()
+            )),
+            (HostStatus::Reconnecting { attempt, error }, _) => out.push_str(&format!(
+                "{:>3}  {:<24} {}\n",
+                row + 1,
+                name,
+                reconnect_status(*attempt, error)
+            )),
+            (_, Some(stats)) => {
+                let fs = match stats.fullest_fs() {
+                    Some((mount_point, used)) => format!(
+                        "{:<18} {}",
+                        truncate(mount_point, 18),
+                        colorize(format!("{:>4.0}%", used), used)
+                    ),
+                    None => format!("{:<24}", "-"),
+                };
+                out.push_str(&format!(
+                    "{:>3}  {:<24} {:>6} {} {}  {} {:>12}/s\n",
+                    row + 1,
+                    name,
+                    stats.load1,
+                    colorize(format!("{:>6.1}", stats.cpu_usage()), stats.cpu_usage()),
+                    colorize(format!("{:>6.1}", stats.mem_usage()), stats.mem_usage()),
+                    fs,
+                    format_bytes(state.net_rate as u64)
+                ))
+            }
+            _ => out.push_str(&format!(
+                "{:>3}  {:<24} {}\n",
+                row + 1,
+                name,
+                "connecting".dimmed()
+            )),
+        }
+        if !state.alerts.is_empty() {
+            out.push_str(&format!("{:>3}  {}\n", "", alert_status(&state.alerts)));
+        }
+        for warning in &state.warnings {
+            out.push_str(&format!("{:>3}  {}\n", "", warning_status(warning)));
+        }
+    }
+    out.push_str(&format!(
+        "\n{}",
+        "Enter a host number to drill down, an empty line to return.".dimmed()
+    ));
+    println!("{}", out);
+}
+
+pub fn alert_status(alerts: &[String]) -> ColoredString {
+    format!("ALERT: {}", alerts.join(", ")).bright_red().bold()
+}
+
+pub fn warning_status(warning: &str) -> ColoredString {
+    format!("warning: {}", warning).yellow()
+}
+
+fn reconnect_status(attempt: u32, error: &str) -> ColoredString {
+    format!("reconnecting (attempt {}): {}", attempt, error).bright_yellow()
+}
+
+fn colorize(text: String, percent: f32) -> ColoredString {
+    if percent >= 90.0 {
+        text.bright_red().bold()
+    } else if percent >= 70.0 {
+        text.bright_yellow()
+    } else {
+        text.bright_white()
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else {
+        let mut short = text.chars().take(width - 1).collect::<String>();
+        short.push('…');
+        short
+    }
+}
@@ -0,0 +1,43 @@
+use crate::sshconnect::TransportKind;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+/// A TOML file listing the hosts to monitor:
+///
+/// ```toml
+/// [[host]]
+/// hostname = "prod-db1"
+///
+/// [[host]]
+/// name = "web"
+/// hostname = "deploy@10.0.0.12:2222"
+/// private_key_file = "~/.ssh/deploy"
+/// jump = ["bastion"]
+/// ```
+#[derive(Deserialize)]
+pub struct Inventory {
+    #[serde(default)]
+    pub host: Vec<InventoryHost>,
+}
+
+#[derive(Deserialize)]
+pub struct InventoryHost {
+    /// Shown in the overview instead of `hostname` when set.
+    pub name: Option<String>,
+    pub hostname: String,
+    pub password: Option<String>,
+    pub private_key_file: Option<String>,
+    #[serde(default)]
+    pub jump: Vec<String>,
+    /// Overrides `--transport` for this host.
+    pub transport: Option<TransportKind>,
+}
+
+pub fn load(path: &str) -> Result<Inventory, Box<dyn Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let inventory =
+        toml::from_str(&contents).map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+    Ok(inventory)
+}
@@ -0,0 +1,31 @@
+//! Collection and parsing of the metrics rsshtop shows, without its interface.
+//!
+//! A `Transport` reaches a host (a remote shell, SFTP or the local machine) and
+//! `collector::collect` runs a set of `Collector`s over it into `Stats`:
+//!
+//! ```no_run
+//! use remote_monitoring::collector::{self, default_collectors};
+//! use remote_monitoring::stats::Stats;
+//! use remote_monitoring::transport::Local;
+//!
+//! let mut collectors = default_collectors();
+//! let mut stats = Stats::default();
+//! collector::collect(&mut collectors, &mut Local, &mut stats).unwrap();
+//! println!("{} is up {}", stats.hostname.trim(), stats.format_uptime());
+//! ```
+//!
+//! Rates and CPU percentages are computed against the previous sample in the
+//! same `Stats`, so they are 0 after the first call.
+
+pub mod batch;
+pub mod collector;
+pub mod disks;
+pub mod error;
+pub mod filesystems;
+pub mod memory;
+pub mod processes;
+pub mod sftp;
+pub mod shell;
+pub mod sshconfig;
+pub mod stats;
+pub mod transport;
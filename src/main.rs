@@ -1,14 +1,28 @@
-use crate::sshconnect::ConnectionType;
-use crate::stats::*;
+use crate::fleet::Fleet;
+use crate::recording::Recorder;
+use crate::sshconnect::{ManagedSession, Sample};
 use clap::Parser;
+use colored::Colorize;
 use crossbeam_channel::{bounded, select, tick, Receiver};
-use ssh2::Session;
-use std::net::TcpStream;
+use remote_monitoring::stats::*;
+use std::io::{self, IsTerminal};
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
 use std::time::Duration;
 
+mod alerts;
 mod cli;
+mod fleet;
+mod inventory;
+mod output;
+mod prometheus;
+mod proxy;
+mod recording;
+mod replay;
 mod sshconnect;
-mod stats;
+mod target;
+mod tui;
 fn ctrl_channel() -> Result<Receiver<()>, ctrlc::Error> {
     let (sender, receiver) = bounded(100);
     ctrlc::set_handler(move || {
@@ -17,60 +31,131 @@ fn ctrl_channel() -> Result<Receiver<()>, ctrlc::Error> {
 
     Ok(receiver)
 }
+fn run_fleet(fleet: Fleet, plain: bool, interval: usize, ctrl_c_events: Receiver<()>) {
+    if plain {
+        fleet::run_plain(fleet, interval, ctrl_c_events);
+    } else if let Err(e) = tui::run(fleet, interval) {
+        eprint!("Terminal error: {}", e);
+    }
+}
+
 fn main() {
     let cli = cli::Cli::parse();
-    let ssh_connection = cli::validate_parameters(&cli);
-    let tcp = TcpStream::connect(ssh_connection.hostname);
-    match tcp {
-        Ok(tcp) => {
-            let session = Session::new();
-            match session {
-                Ok(mut session) => {
-                    session.set_tcp_stream(tcp);
-                    session.handshake().expect("Handshake failed!");
-
-                    let auth: Result<(), ssh2::Error> = match ssh_connection.connection_type {
-                        ConnectionType::Agent => session.userauth_agent(ssh_connection.hostname),
-                        ConnectionType::PrivateKey => session.userauth_pubkey_file(
-                            ssh_connection.user,
-                            None,
-                            std::path::Path::new(ssh_connection.private_key_path.unwrap()),
-                            None,
-                        ),
-                        ConnectionType::Password => session.userauth_password(
-                            ssh_connection.user,
-                            ssh_connection.password.unwrap(),
-                        ),
-                    };
-                    match auth {
-                        Ok(_) => {
-                            let ctrl_c_events = ctrl_channel().unwrap();
-                            let ticks = tick(Duration::from_secs(cli.interval as u64));
-                            let mut stats = Stats::default();
-                            loop {
-                                select! {
-                                    recv(ticks) -> _ => {
-                                        if let Ok(()) = stats.get_all_stats(&session){
-                                            println!("{}",stats);
-                                        };
-                                    }
-                                    recv(ctrl_c_events) -> _ => {
-                                        println!("Goodbye!");
-                                        break;
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprint!("Authentication failed: {}", e);
-                        }
-                    }
-                }
-                Err(e) => eprint!("Failed to create session: {}", e),
-            };
-        }
+    if let Some(cli::Command::Replay { file, speed, plain }) = &cli.command {
+        let plain = *plain || !io::stdout().is_terminal();
+        replay::run(file, *speed, plain, ctrl_channel().unwrap());
+        return;
+    }
+    let mut connections = match cli::validate_parameters(&cli) {
+        Ok(connections) => connections,
         Err(e) => {
-            eprint!("Failed to connect: {}", e);
+            eprint!("Invalid host: {}", e);
+            return;
         }
     };
+    let ctrl_c_events = ctrl_channel().unwrap();
+    let plain = cli.plain || !io::stdout().is_terminal();
+    let mut sessions = if connections.len() > 1 {
+        fleet::connect_all(connections, cli.refuse_unknown_hosts)
+    } else {
+        // A single host has to be reachable at start, several are retried in the background.
+        let ssh_connection = connections.remove(0);
+        if ssh_connection.local {
+            vec![ManagedSession::local(ssh_connection)]
+        } else {
+            match sshconnect::open_session(&ssh_connection, cli.refuse_unknown_hosts) {
+                Ok(session) => vec![ManagedSession::new(ssh_connection, Ok(session))],
+                Err(e) => {
+                    eprint!("{}", e);
+                    return;
+                }
+            }
+        }
+    };
+
+    if let Some(path) = cli.record.as_deref() {
+        let recorder = match Recorder::create(path) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                eprint!("{}", e);
+                return;
+            }
+        };
+        for managed in &mut sessions {
+            managed.set_recorder(recorder.clone());
+        }
+    }
+
+    if let Some(path) = cli.alerts.as_deref() {
+        let config = match alerts::load(path) {
+            Ok(config) => Arc::new(config),
+            Err(e) => {
+                eprint!("{}", e);
+                return;
+            }
+        };
+        for managed in &mut sessions {
+            managed.set_alerts(Arc::clone(&config));
+        }
+    }
+
+    if let Some(address) = cli.serve_prometheus.as_deref() {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen on {}: {}", address, e);
+                process::exit(1);
+            }
+        };
+        prometheus::serve(
+            Fleet::start(sessions),
+            listener,
+            cli.interval(),
+            ctrl_c_events,
+        );
+        return;
+    }
+    if let Some(format) = cli.output {
+        output::run(
+            Fleet::start(sessions),
+            format,
+            cli.interval(),
+            ctrl_c_events,
+        );
+        return;
+    }
+    if sessions.len() > 1 || !plain {
+        run_fleet(Fleet::start(sessions), plain, cli.interval(), ctrl_c_events);
+        return;
+    }
+
+    let mut managed = sessions.remove(0);
+    let ticks = tick(Duration::from_secs(managed.connection().interval as u64));
+    let mut stats = Stats::default();
+    loop {
+        select! {
+            recv(ticks) -> _ => {
+                match managed.sample(&mut stats) {
+                    Sample::Collected => println!("{}", stats),
+                    Sample::Failed(e) => println!("{}{}", stats, format!("error: {}", e).bright_red()),
+                    Sample::Reconnecting { attempt, error } => println!(
+                        "{}{}",
+                        stats,
+                        format!("reconnecting (attempt {}): {}", attempt, error).bright_red()
+                    ),
+                }
+                let alerts = managed.firing_alerts();
+                if !alerts.is_empty() {
+                    println!("{}\n", fleet::alert_status(&alerts));
+                }
+                for warning in managed.warnings() {
+                    println!("{}\n", fleet::warning_status(&warning));
+                }
+            }
+            recv(ctrl_c_events) -> _ => {
+                println!("Goodbye!");
+                break;
+            }
+        }
+    }
 }
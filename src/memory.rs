@@ -0,0 +1,216 @@
+use crate::error::MonitorError;
+use std::collections::BTreeMap;
+
+/// Every field of /proc/meminfo, by its name there (`MemTotal`, `SReclaimable`).
+/// Sizes are in bytes; the `HugePages_*` fields have no unit and count pages.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct MemInfo {
+    fields: BTreeMap<String, u64>,
+}
+
+impl MemInfo {
+    pub fn parse(output: &str) -> Result<Self, MonitorError> {
+        let mut fields = BTreeMap::new();
+        for (index, line) in output.lines().enumerate() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let error = |message: String| MonitorError::parse("/proc/meminfo", index + 1, message);
+            let mut parts = value.split_whitespace();
+            let number = parts
+                .next()
+                .ok_or_else(|| error(format!("{} has no value", name)))?
+                .parse::<u64>()
+                .map_err(|e| error(format!("{}: {}", name, e)))?;
+            let value = match parts.next() {
+                None => number,
+                Some("kB") => number * 1024,
+                Some(unit) => return Err(error(format!("{}: unknown unit {}", name, unit))),
+            };
+            fields.insert(name.trim().to_string(), value);
+        }
+        Ok(MemInfo { fields })
+    }
+
+    /// A field by its /proc/meminfo name, `None` if this kernel doesn't report it.
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.fields.get(name).copied()
+    }
+
+    pub fn insert(&mut self, name: &str, value: u64) {
+        self.fields.insert(name.to_string(), value);
+    }
+
+    fn field(&self, name: &str) -> u64 {
+        self.get(name).unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.field("MemTotal")
+    }
+
+    pub fn free(&self) -> u64 {
+        self.field("MemFree")
+    }
+
+    pub fn buffers(&self) -> u64 {
+        self.field("Buffers")
+    }
+
+    /// The page cache, without reclaimable slab.
+    pub fn cached(&self) -> u64 {
+        self.field("Cached")
+    }
+
+    /// Slab that is given back under pressure (dentries, inodes).
+    pub fn slab_reclaimable(&self) -> u64 {
+        self.field("SReclaimable")
+    }
+
+    pub fn slab(&self) -> u64 {
+        self.field("Slab")
+    }
+
+    /// tmpfs and shared memory. Counted in `cached` but can't be dropped.
+    pub fn shared(&self) -> u64 {
+        self.field("Shmem")
+    }
+
+    /// What `free` shows as buff/cache.
+    pub fn buff_cache(&self) -> u64 {
+        self.buffers() + self.cached() + self.slab_reclaimable()
+    }
+
+    /// The kernel's estimate of what can be allocated without swapping. Kernels
+    /// before 3.14 have no MemAvailable; free memory plus buff/cache stands in.
+    pub fn available(&self) -> u64 {
+        self.get("MemAvailable")
+            .unwrap_or_else(|| self.free() + self.buff_cache())
+            .min(self.total())
+    }
+
+    /// Total less available, as `free` computes it.
+    pub fn used(&self) -> u64 {
+        self.total() - self.available()
+    }
+
+    /// Used share of the memory in percent.
+    pub fn usage(&self) -> f32 {
+        if self.total() == 0 {
+            return 0.0;
+        }
+        self.used() as f32 / self.total() as f32 * 100.0
+    }
+
+    /// Waiting to be written back to disk.
+    pub fn dirty(&self) -> u64 {
+        self.field("Dirty")
+    }
+
+    /// Being written back to disk.
+    pub fn writeback(&self) -> u64 {
+        self.field("Writeback")
+    }
+
+    /// Memory the allocations so far would need if all were touched.
+    pub fn committed(&self) -> u64 {
+        self.field("Committed_AS")
+    }
+
+    pub fn commit_limit(&self) -> u64 {
+        self.field("CommitLimit")
+    }
+
+    pub fn huge_page_size(&self) -> u64 {
+        self.field("Hugepagesize")
+    }
+
+    /// The huge page pool, in bytes.
+    pub fn huge_pages_total(&self) -> u64 {
+        self.field("HugePages_Total") * self.huge_page_size()
+    }
+
+    pub fn huge_pages_free(&self) -> u64 {
+        self.field("HugePages_Free") * self.huge_page_size()
+    }
+
+    pub fn swap_total(&self) -> u64 {
+        self.field("SwapTotal")
+    }
+
+    pub fn swap_free(&self) -> u64 {
+        self.field("SwapFree")
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total().saturating_sub(self.swap_free())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: u64 = 1024;
+
+    #[test]
+    fn parses_sizes_and_page_counts() {
+        let memory = MemInfo::parse(
+            "MemTotal:       16000000 kB\nMemFree:         1000000 kB\nMemAvailable:    9000000 kB\nHugePages_Total:     512\nHugePages_Free:      128\nHugepagesize:       2048 kB\n",
+        )
+        .unwrap();
+        assert_eq!(memory.total(), 16_000_000 * KIB);
+        assert_eq!(memory.get("HugePages_Total"), Some(512));
+        assert_eq!(memory.get("SReclaimable"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(MemInfo::parse("MemTotal:\n").is_err());
+        assert!(MemInfo::parse("MemTotal: many kB\n").is_err());
+        assert!(MemInfo::parse("MemTotal: 1000 MB\n").is_err());
+    }
+
+    #[test]
+    fn huge_pages_are_counted_in_bytes() {
+        let memory = MemInfo::parse(
+            "HugePages_Total:    4096\nHugePages_Free:     1210\nHugePages_Rsvd:        0\nHugepagesize:       2048 kB\n",
+        )
+        .unwrap();
+        assert_eq!(memory.huge_page_size(), 2048 * KIB);
+        assert_eq!(memory.huge_pages_total(), 4096 * 2048 * KIB);
+        assert_eq!(memory.huge_pages_free(), 1210 * 2048 * KIB);
+        assert_eq!(MemInfo::default().huge_pages_total(), 0);
+    }
+
+    #[test]
+    fn used_is_total_less_available() {
+        let memory = MemInfo::parse(
+            "MemTotal:        8000000 kB\nMemFree:          500000 kB\nMemAvailable:    6000000 kB\nBuffers:          100000 kB\nCached:          3000000 kB\nSReclaimable:     200000 kB\n",
+        )
+        .unwrap();
+        assert_eq!(memory.available(), 6_000_000 * KIB);
+        assert_eq!(memory.used(), 2_000_000 * KIB);
+        assert_eq!(memory.usage(), 25.0);
+    }
+
+    #[test]
+    fn available_falls_back_to_free_and_buff_cache() {
+        // Kernels before 3.14, like RHEL 6, don't report MemAvailable.
+        let memory = MemInfo::parse(
+            "MemTotal:        8000000 kB\nMemFree:          500000 kB\nBuffers:          100000 kB\nCached:          3000000 kB\nSReclaimable:     200000 kB\n",
+        )
+        .unwrap();
+        assert_eq!(memory.buff_cache(), 3_300_000 * KIB);
+        assert_eq!(memory.available(), 3_800_000 * KIB);
+        assert_eq!(memory.used(), 4_200_000 * KIB);
+    }
+
+    #[test]
+    fn available_is_at_most_total() {
+        let memory = MemInfo::parse("MemTotal:  4000000 kB\nMemAvailable: 5000000 kB\n").unwrap();
+        assert_eq!(memory.available(), memory.total());
+        assert_eq!(memory.used(), 0);
+        assert_eq!(MemInfo::default().usage(), 0.0);
+    }
+}
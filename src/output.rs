@@ -0,0 +1,635 @@
+use crate::fleet::{Fleet, HostStatus};
+use clap::ValueEnum;
+use crossbeam_channel::{select, tick, Receiver};
+use remote_monitoring::disks::DiskInfo;
+use remote_monitoring::error::{MonitorError, SectionError};
+use remote_monitoring::filesystems::FileSystemInfo;
+use remote_monitoring::memory::MemInfo;
+use remote_monitoring::processes::ProcessInfo;
+use remote_monitoring::stats::{CpuInfo, NetIntfInfo, Stats};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Bumped when a field is renamed, removed or changes meaning. New fields keep the version.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// One pretty-printed snapshot, or an array of them with several hosts, then exit
+    Json,
+    /// One snapshot object per line, a line per host and tick
+    Ndjson,
+}
+
+/// One sample of one host. Sizes are in bytes, rates per second, shares in percent.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub schema_version: u32,
+    /// RFC 3339, UTC.
+    pub timestamp: String,
+    /// The host as given on the command line or in the inventory.
+    pub host: String,
+    /// What the host calls itself.
+    pub hostname: String,
+    pub uptime_seconds: f64,
+    pub load: Load,
+    pub tasks: Tasks,
+    pub cpu: Cpu,
+    pub cores: Vec<Core>,
+    pub memory: Memory,
+    pub filesystems: Vec<Filesystem>,
+    pub disks: Vec<Disk>,
+    pub interfaces: Vec<Interface>,
+    pub processes: Vec<Process>,
+    /// Collectors that failed in this sample. Their figures are from the sample before.
+    #[serde(default)]
+    pub failed: Vec<Failure>,
+    /// Set in recordings when no sample could be taken; the figures are then
+    /// from the last sample that was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The reconnect attempt when the sample failed because the session was lost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconnect_attempt: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Load {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tasks {
+    pub running: u64,
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Cpu {
+    pub user: f32,
+    pub nice: f32,
+    pub system: f32,
+    pub idle: f32,
+    pub iowait: f32,
+    pub irq: f32,
+    pub softirq: f32,
+    pub steal: f32,
+    pub guest: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Core {
+    pub core: usize,
+    #[serde(flatten)]
+    pub cpu: Cpu,
+}
+
+/// Memory figures as `free` shows them. Since schema 2, `used` is `total - available`
+/// rather than `total - free - buffers - cached`.
+#[derive(Serialize, Deserialize)]
+pub struct Memory {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+    #[serde(default)]
+    pub available: u64,
+    pub buffers: u64,
+    /// The page cache, without reclaimable slab.
+    pub cached: u64,
+    #[serde(default)]
+    pub shared: u64,
+    #[serde(default)]
+    pub slab: u64,
+    #[serde(default)]
+    pub slab_reclaimable: u64,
+    #[serde(default)]
+    pub dirty: u64,
+    #[serde(default)]
+    pub writeback: u64,
+    #[serde(default)]
+    pub committed: u64,
+    #[serde(default)]
+    pub commit_limit: u64,
+    #[serde(default)]
+    pub hugepages_total: u64,
+    #[serde(default)]
+    pub hugepages_free: u64,
+    #[serde(default)]
+    pub hugepage_size: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Filesystem {
+    pub mount_point: String,
+    pub device: String,
+    pub fs_type: String,
+    pub size: u64,
+    pub used: u64,
+    pub free: u64,
+    pub used_percent: f32,
+    pub inodes: u64,
+    pub inodes_used: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Disk {
+    pub name: String,
+    pub read_bytes_per_second: f64,
+    pub write_bytes_per_second: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    pub await_ms: f64,
+    pub util_percent: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Interface {
+    pub name: String,
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes_per_second: f64,
+    pub tx_bytes_per_second: f64,
+    pub rx_packets_per_second: f64,
+    pub tx_packets_per_second: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Process {
+    pub pid: u32,
+    pub user: String,
+    pub state: String,
+    pub cpu_percent: f32,
+    pub rss: u64,
+    pub command: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Failure {
+    pub section: String,
+    pub error: String,
+}
+
+impl Snapshot {
+    pub fn new(host: &str, stats: &Stats) -> Self {
+        let mut interfaces = stats
+            .net_intf
+            .iter()
+            .map(|(name, intf)| Interface {
+                name: name.clone(),
+                ipv4: Some(intf.ipv4.clone()).filter(|ip| !ip.is_empty()),
+                ipv6: Some(intf.ipv6.clone()).filter(|ip| !ip.is_empty()),
+                rx_bytes: intf.rx,
+                tx_bytes: intf.tx,
+                rx_packets: intf.rx_packets,
+                tx_packets: intf.tx_packets,
+                rx_bytes_per_second: intf.rx_rate,
+                tx_bytes_per_second: intf.tx_rate,
+                rx_packets_per_second: intf.rx_packet_rate,
+                tx_packets_per_second: intf.tx_packet_rate,
+            })
+            .collect::<Vec<_>>();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Snapshot {
+            schema_version: SCHEMA_VERSION,
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            host: host.to_string(),
+            hostname: stats.hostname.trim().to_string(),
+            uptime_seconds: stats.uptime,
+            load: Load {
+                one: stats.load1.parse().unwrap_or(0.0),
+                five: stats.load5.parse().unwrap_or(0.0),
+                fifteen: stats.load10.parse().unwrap_or(0.0),
+            },
+            tasks: Tasks {
+                running: stats.running_procs.trim().parse().unwrap_or(0),
+                total: stats.total_procs.trim().parse().unwrap_or(0),
+            },
+            cpu: Cpu::from(&stats.cpu),
+            cores: stats
+                .cores
+                .iter()
+                .map(|(core, cpu)| Core {
+                    core: *core,
+                    cpu: Cpu::from(cpu),
+                })
+                .collect(),
+            memory: Memory::from(&stats.memory),
+            filesystems: stats
+                .fs_infos
+                .iter()
+                .map(|fs| Filesystem {
+                    mount_point: fs.mount_point.clone(),
+                    device: fs.device.clone(),
+                    fs_type: fs.fs_type.clone(),
+                    size: fs.size,
+                    used: fs.used,
+                    free: fs.free,
+                    used_percent: fs.used_percent,
+                    inodes: fs.inodes,
+                    inodes_used: fs.inodes_used,
+                })
+                .collect(),
+            disks: stats
+                .disks
+                .iter()
+                .map(|disk| Disk {
+                    name: disk.name.clone(),
+                    read_bytes_per_second: disk.read_rate,
+                    write_bytes_per_second: disk.write_rate,
+                    read_iops: disk.read_iops,
+                    write_iops: disk.write_iops,
+                    await_ms: disk.await_ms,
+                    util_percent: disk.util,
+                })
+                .collect(),
+            interfaces,
+            processes: stats
+                .processes
+                .iter()
+                .map(|process| Process {
+                    pid: process.pid,
+                    user: process.user.clone(),
+                    state: process.state.to_string(),
+                    cpu_percent: process.cpu,
+                    rss: process.rss,
+                    command: process.command.clone(),
+                })
+                .collect(),
+            failed: stats
+                .failed
+                .iter()
+                .map(|failed| Failure {
+                    section: failed.section.clone(),
+                    error: failed.error.to_string(),
+                })
+                .collect(),
+            error: None,
+            reconnect_attempt: None,
+        }
+    }
+
+    /// The `Stats` this snapshot was taken from, as far as the schema carries it.
+    /// Baselines for the next sample are not part of it.
+    pub fn to_stats(&self) -> Stats {
+        Stats {
+            uptime: self.uptime_seconds,
+            hostname: self.hostname.clone(),
+            load1: format!("{:.2}", self.load.one),
+            load5: format!("{:.2}", self.load.five),
+            load10: format!("{:.2}", self.load.fifteen),
+            running_procs: self.tasks.running.to_string(),
+            total_procs: self.tasks.total.to_string(),
+            memory: self.memory.to_mem_info(self.schema_version),
+            fs_infos: self
+                .filesystems
+                .iter()
+                .map(|fs| FileSystemInfo {
+                    device: fs.device.clone(),
+                    fs_type: fs.fs_type.clone(),
+                    mount_point: fs.mount_point.clone(),
+                    size: fs.size,
+                    used: fs.used,
+                    free: fs.free,
+                    used_percent: fs.used_percent,
+                    inodes: fs.inodes,
+                    inodes_used: fs.inodes_used,
+                })
+                .collect(),
+            disks: self
+                .disks
+                .iter()
+                .map(|disk| DiskInfo {
+                    name: disk.name.clone(),
+                    partition: false,
+                    read_rate: disk.read_bytes_per_second,
+                    write_rate: disk.write_bytes_per_second,
+                    read_iops: disk.read_iops,
+                    write_iops: disk.write_iops,
+                    await_ms: disk.await_ms,
+                    util: disk.util_percent,
+                })
+                .collect(),
+            net_intf: self
+                .interfaces
+                .iter()
+                .map(|intf| {
+                    let info = NetIntfInfo {
+                        ipv4: intf.ipv4.clone().unwrap_or_default(),
+                        ipv6: intf.ipv6.clone().unwrap_or_default(),
+                        rx: intf.rx_bytes,
+                        tx: intf.tx_bytes,
+                        rx_packets: intf.rx_packets,
+                        tx_packets: intf.tx_packets,
+                        rx_rate: intf.rx_bytes_per_second,
+                        tx_rate: intf.tx_bytes_per_second,
+                        rx_packet_rate: intf.rx_packets_per_second,
+                        tx_packet_rate: intf.tx_packets_per_second,
+                        sampled_at: None,
+                    };
+                    (intf.name.clone(), info)
+                })
+                .collect(),
+            cpu: CpuInfo::from(&self.cpu),
+            cores: self
+                .cores
+                .iter()
+                .map(|core| (core.core, CpuInfo::from(&core.cpu)))
+                .collect(),
+            processes: self
+                .processes
+                .iter()
+                .map(|process| ProcessInfo {
+                    pid: process.pid,
+                    user: process.user.clone(),
+                    state: process.state.chars().next().unwrap_or('?'),
+                    cpu: process.cpu_percent,
+                    rss: process.rss,
+                    command: process.command.clone(),
+                })
+                .collect(),
+            failed: self
+                .failed
+                .iter()
+                .map(|failed| SectionError {
+                    section: failed.section.clone(),
+                    error: MonitorError::Recorded(failed.error.clone()),
+                })
+                .collect(),
+            ..Stats::default()
+        }
+    }
+}
+
+impl From<&Cpu> for CpuInfo {
+    fn from(cpu: &Cpu) -> Self {
+        CpuInfo {
+            user: cpu.user,
+            nice: cpu.nice,
+            system: cpu.system,
+            idle: cpu.idle,
+            iowait: cpu.iowait,
+            irq: cpu.irq,
+            soft_irq: cpu.softirq,
+            steal: cpu.steal,
+            guest: cpu.guest,
+        }
+    }
+}
+
+impl From<&CpuInfo> for Cpu {
+    fn from(cpu: &CpuInfo) -> Self {
+        Cpu {
+            user: cpu.user,
+            nice: cpu.nice,
+            system: cpu.system,
+            idle: cpu.idle,
+            iowait: cpu.iowait,
+            irq: cpu.irq,
+            softirq: cpu.soft_irq,
+            steal: cpu.steal,
+            guest: cpu.guest,
+        }
+    }
+}
+
+impl From<&MemInfo> for Memory {
+    fn from(memory: &MemInfo) -> Self {
+        Memory {
+            total: memory.total(),
+            free: memory.free(),
+            used: memory.used(),
+            available: memory.available(),
+            buffers: memory.buffers(),
+            cached: memory.cached(),
+            shared: memory.shared(),
+            slab: memory.slab(),
+            slab_reclaimable: memory.slab_reclaimable(),
+            dirty: memory.dirty(),
+            writeback: memory.writeback(),
+            committed: memory.committed(),
+            commit_limit: memory.commit_limit(),
+            hugepages_total: memory.huge_pages_total(),
+            hugepages_free: memory.huge_pages_free(),
+            hugepage_size: memory.huge_page_size(),
+            swap_total: memory.swap_total(),
+            swap_free: memory.swap_free(),
+        }
+    }
+}
+
+impl Memory {
+    /// The /proc/meminfo fields these figures came from. Schema 1 has no
+    /// `available`, so `used` is worked out from buffers and cache as it was then.
+    fn to_mem_info(&self, schema_version: u32) -> MemInfo {
+        let mut memory = MemInfo::default();
+        let pages = |bytes: u64| bytes.checked_div(self.hugepage_size).unwrap_or(0);
+        for (name, value) in [
+            ("MemTotal", self.total),
+            ("MemFree", self.free),
+            ("Buffers", self.buffers),
+            ("Cached", self.cached),
+            ("Shmem", self.shared),
+            ("Slab", self.slab),
+            ("SReclaimable", self.slab_reclaimable),
+            ("Dirty", self.dirty),
+            ("Writeback", self.writeback),
+            ("Committed_AS", self.committed),
+            ("CommitLimit", self.commit_limit),
+            ("HugePages_Total", pages(self.hugepages_total)),
+            ("HugePages_Free", pages(self.hugepages_free)),
+            ("Hugepagesize", self.hugepage_size),
+            ("SwapTotal", self.swap_total),
+            ("SwapFree", self.swap_free),
+        ] {
+            memory.insert(name, value);
+        }
+        if schema_version >= 2 {
+            memory.insert("MemAvailable", self.available);
+        }
+        memory
+    }
+}
+
+/// Writes snapshots to stdout and errors to stderr. The first sample after a
+/// (re)connect only sets the CPU and rate baselines and is not written.
+/// `Json` waits until every host has a snapshot or an error and then writes
+/// a single document; `Ndjson` writes each snapshot on a line of its own.
+pub fn run(fleet: Fleet, format: OutputFormat, interval: usize, ctrl_c_events: Receiver<()>) {
+    let ticks = tick(Duration::from_secs(interval as u64));
+    let mut seen = vec![0; fleet.states.len()];
+    let mut errors: Vec<Option<String>> = vec![None; fleet.states.len()];
+    let mut warnings: Vec<Vec<String>> = vec![Vec::new(); fleet.states.len()];
+    let mut done = vec![false; fleet.states.len()];
+    let mut snapshots: Vec<Option<Snapshot>> = (0..fleet.states.len()).map(|_| None).collect();
+    fleet.tick();
+
+    loop {
+        select! {
+            recv(ticks) -> _ => fleet.tick(),
+            recv(fleet.updates) -> _ => {
+                for (index, state) in fleet.states.iter().enumerate() {
+                    let state = state.lock().unwrap();
+                    let error = match &state.status {
+                        HostStatus::Failed(e) => Some(e.clone()),
+                        HostStatus::Reconnecting { attempt, error } => {
+                            Some(format!("reconnecting (attempt {}): {}", attempt, error))
+                        }
+                        _ => None,
+                    };
+                    if error.is_some() && error != errors[index] && !done[index] {
+                        eprintln!("{}: {}", state.name, error.as_deref().unwrap_or_default());
+                        if let OutputFormat::Json = format {
+                            done[index] = true;
+                        }
+                    }
+                    errors[index] = error;
+                    for warning in &state.warnings {
+                        if !warnings[index].contains(warning) {
+                            eprintln!("{}: warning: {}", state.name, warning);
+                        }
+                    }
+                    warnings[index] = state.warnings.clone();
+
+                    if state.samples == seen[index] {
+                        continue;
+                    }
+                    seen[index] = state.samples;
+                    let (Some(stats), true) = (&state.stats, state.samples >= 2) else {
+                        continue;
+                    };
+                    let snapshot = Snapshot::new(&state.name, stats);
+                    match format {
+                        OutputFormat::Json if !done[index] => {
+                            snapshots[index] = Some(snapshot);
+                            done[index] = true;
+                        }
+                        OutputFormat::Json => {}
+                        OutputFormat::Ndjson => {
+                            println!("{}", serde_json::to_string(&snapshot).unwrap())
+                        }
+                    }
+                }
+                if let OutputFormat::Json = format {
+                    if done.iter().all(|done| *done) {
+                        let several = snapshots.len() > 1;
+                        let snapshots = snapshots.into_iter().flatten().collect::<Vec<_>>();
+                        if let Some(document) = json_document(&snapshots, several) {
+                            println!("{}", document);
+                        }
+                        break;
+                    }
+                }
+            }
+            recv(ctrl_c_events) -> _ => break,
+        }
+    }
+}
+
+/// The `Json` output: the snapshot itself for a single host, an array in host
+/// order for several (without the hosts that failed), nothing if none succeeded.
+fn json_document(snapshots: &[Snapshot], several: bool) -> Option<String> {
+    let document = match snapshots {
+        [] => return None,
+        [snapshot] if !several => serde_json::to_string_pretty(snapshot),
+        _ => serde_json::to_string_pretty(snapshots),
+    };
+    Some(document.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_is_one_document() {
+        let snapshot = |host: &str| Snapshot::new(host, &Stats::default());
+        assert_eq!(json_document(&[], true), None);
+
+        let single = json_document(&[snapshot("db1")], false).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&single).unwrap();
+        assert_eq!(value["host"], "db1");
+
+        // With several hosts an array, even when only one of them answered.
+        for snapshots in [
+            vec![snapshot("db1"), snapshot("web")],
+            vec![snapshot("web")],
+        ] {
+            let document = json_document(&snapshots, true).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&document).unwrap();
+            let hosts = value
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|snapshot| snapshot["host"].as_str().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(hosts.last(), Some(&"web"));
+            assert_eq!(hosts.len(), snapshots.len());
+        }
+    }
+
+    #[test]
+    fn failed_sections_survive_a_recording() {
+        let stats = Stats {
+            failed: vec![SectionError {
+                section: "disks".to_string(),
+                error: MonitorError::Read {
+                    path: "/proc/diskstats".to_string(),
+                    message: "Permission denied".to_string(),
+                },
+            }],
+            ..Stats::default()
+        };
+        let line = serde_json::to_string(&Snapshot::new("db1", &stats)).unwrap();
+        let snapshot: Snapshot = serde_json::from_str(&line).unwrap();
+        let replayed = snapshot.to_stats();
+        assert_eq!(replayed.failed.len(), 1);
+        assert_eq!(
+            replayed.failed[0].to_string(),
+            "disks: Failed to read /proc/diskstats: Permission denied"
+        );
+        assert!(!line.contains("\"error\":null"));
+    }
+
+    #[test]
+    fn schema_1_memory_keeps_its_used_figure() {
+        // Version 1 counted used as total - free - buffers - cached.
+        let memory: Memory = serde_json::from_str(
+            r#"{"total":8000,"free":1000,"used":3000,"buffers":500,"cached":3500,"swap_total":2000,"swap_free":1500}"#,
+        )
+        .unwrap();
+        let mem_info = memory.to_mem_info(1);
+        assert_eq!(mem_info.get("MemAvailable"), None);
+        assert_eq!(mem_info.available(), 5000);
+        assert_eq!(mem_info.used(), 3000);
+        assert_eq!(mem_info.swap_used(), 500);
+    }
+
+    #[test]
+    fn schema_2_memory_round_trips() {
+        let mem_info = MemInfo::parse(
+            "MemTotal:        8000000 kB\nMemFree:          500000 kB\nMemAvailable:    6000000 kB\nBuffers:          100000 kB\nCached:          3000000 kB\nSReclaimable:     200000 kB\nHugePages_Total:     512\nHugePages_Free:      128\nHugepagesize:       2048 kB\n",
+        )
+        .unwrap();
+        let memory = Memory::from(&mem_info);
+        assert_eq!(memory.used, 2_000_000 * 1024);
+
+        let replayed = memory.to_mem_info(SCHEMA_VERSION);
+        assert_eq!(replayed.available(), mem_info.available());
+        assert_eq!(replayed.used(), mem_info.used());
+        assert_eq!(replayed.huge_pages_total(), mem_info.huge_pages_total());
+        assert_eq!(replayed.huge_pages_free(), mem_info.huge_pages_free());
+    }
+}
@@ -0,0 +1,370 @@
+use crate::error::MonitorError;
+use crate::transport::Transport;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Reads everything needed for the process table in one exec: uptime and clock
+/// ticks for the CPU percentages, then every `stat`, the Uid/VmRSS lines of every
+/// `status`, every `cmdline` and the passwd file for user names. Every line of
+/// the files comes out prefixed with its path by `grep -H`, so a newline in a
+/// process name or argument can't start a marker or another process's entry.
+/// Where grep can't be told to print NULs (busybox), command lines are read
+/// one by one instead.
+pub const PROCESS_COMMAND: &str = "cd /proc && cat uptime; \
+    getconf CLK_TCK 2>/dev/null || echo 100; \
+    echo '@@stat'; LC_ALL=C grep -H '' [0-9]*/stat 2>/dev/null; \
+    echo '@@status'; grep -H -E '^(Uid|VmRSS):' [0-9]*/status 2>/dev/null; \
+    echo '@@cmdline'; \
+    if echo | grep -a '' >/dev/null 2>&1; then \
+    grep -a -H '' [0-9]*/cmdline 2>/dev/null | tr '\\0' ' ' | cut -c 1-540; \
+    else for f in [0-9]*/cmdline; do \
+    printf '%s:' \"$f\"; head -c 512 \"$f\" 2>/dev/null | tr '\\0\\n' '  '; echo; \
+    done; fi; \
+    echo '@@passwd'; cat /etc/passwd 2>/dev/null";
+
+/// Clock ticks per second of /proc/<pid>/stat times, where `getconf CLK_TCK`
+/// can't be run. Linux reports 100 on every common architecture.
+const CLOCK_TICKS: u32 = 100;
+
+#[derive(Clone)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub user: String,
+    pub state: char,
+    /// Percent of one core, so a busy multi-threaded process can exceed 100.
+    pub cpu: f32,
+    /// Resident set size in bytes.
+    pub rss: u64,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessSort {
+    Cpu,
+    Memory,
+    Pid,
+}
+
+/// CPU time per pid and the uptime it was read at, for the next sample's percentages.
+#[derive(Default, Clone)]
+pub struct ProcessTicks {
+    pub uptime: f64,
+    pub ticks: HashMap<u32, u64>,
+}
+
+/// Parses the output of `PROCESS_COMMAND`. CPU percentages are computed against
+/// `prev` and stay 0 for processes that were not present in it.
+pub fn parse(output: &str, prev: &ProcessTicks) -> (Vec<ProcessInfo>, ProcessTicks) {
+    let mut sections: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut section = "header";
+    // Markers are taken once and in order. A process name can hold a newline,
+    // and `stat` files are read as they are.
+    let mut markers = ["stat", "status", "cmdline", "passwd"]
+        .into_iter()
+        .peekable();
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("@@") {
+            if markers.next_if_eq(&name).is_some() {
+                section = name;
+                continue;
+            }
+        }
+        sections.entry(section).or_default().push(line);
+    }
+    let section = |name: &str| sections.get(name).cloned().unwrap_or_default();
+
+    let header = section("header");
+    let uptime = header
+        .first()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|uptime| uptime.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    let clock_ticks = header
+        .get(1)
+        .and_then(|line| line.trim().parse::<f64>().ok())
+        .unwrap_or(100.0);
+    let elapsed = uptime - prev.uptime;
+
+    let users = section("passwd")
+        .into_iter()
+        .filter_map(|line| {
+            let fields = line.split(':').collect::<Vec<_>>();
+            Some((fields.get(2)?.parse::<u32>().ok()?, fields[0].to_string()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut uids: HashMap<u32, u32> = HashMap::new();
+    let mut rss: HashMap<u32, u64> = HashMap::new();
+    for line in section("status") {
+        // 1234/status:Uid:	1000	1000	1000	1000
+        let Some((path, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(pid) = pid_of(path) else {
+            continue;
+        };
+        let fields = rest.split_whitespace().collect::<Vec<_>>();
+        match fields.first() {
+            Some(&"Uid:") => {
+                if let Some(Ok(uid)) = fields.get(1).map(|uid| uid.parse()) {
+                    uids.insert(pid, uid);
+                }
+            }
+            Some(&"VmRSS:") => {
+                if let Some(Ok(kb)) = fields.get(1).map(|kb| kb.parse::<u64>()) {
+                    rss.insert(pid, kb * 1024);
+                }
+            }
+            _ => continue,
+        }
+    }
+
+    let cmdlines = prefixed_files(&section("cmdline"));
+    let mut ticks = HashMap::new();
+    let mut processes = Vec::new();
+    for (pid, stat) in prefixed_files(&section("stat")) {
+        // The command name is in parentheses and may itself contain spaces or parentheses.
+        let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else {
+            continue;
+        };
+        let name = &stat[open + 1..close];
+        let fields = stat[close + 1..].split_whitespace().collect::<Vec<_>>();
+        // fields[0] is field 3 (state), utime and stime are fields 14 and 15.
+        if fields.len() < 13 {
+            continue;
+        }
+        let cpu_ticks =
+            fields[11].parse::<u64>().unwrap_or(0) + fields[12].parse::<u64>().unwrap_or(0);
+        ticks.insert(pid, cpu_ticks);
+
+        let cpu = match prev.ticks.get(&pid) {
+            Some(prev_ticks) if elapsed > 0.0 => {
+                cpu_ticks.saturating_sub(*prev_ticks) as f64 / clock_ticks / elapsed * 100.0
+            }
+            _ => 0.0,
+        };
+        let command = match cmdlines.get(&pid).map(|cmdline| cmdline.trim()) {
+            Some(cmdline) if !cmdline.is_empty() => cmdline.to_string(),
+            // Kernel threads have no command line.
+            _ => format!("[{}]", name),
+        };
+        let user = match uids.get(&pid) {
+            Some(uid) => users.get(uid).cloned().unwrap_or_else(|| uid.to_string()),
+            None => String::from("?"),
+        };
+
+        processes.push(ProcessInfo {
+            pid,
+            user,
+            state: fields[0].chars().next().unwrap_or('?'),
+            cpu: cpu as f32,
+            rss: rss.get(&pid).copied().unwrap_or(0),
+            command,
+        });
+    }
+    sort(&mut processes, ProcessSort::Cpu);
+
+    (processes, ProcessTicks { uptime, ticks })
+}
+
+pub fn sort(processes: &mut [ProcessInfo], by: ProcessSort) {
+    match by {
+        ProcessSort::Cpu => {
+            processes.sort_by(|a, b| b.cpu.total_cmp(&a.cpu).then(a.pid.cmp(&b.pid)))
+        }
+        ProcessSort::Memory => processes.sort_by(|a, b| b.rss.cmp(&a.rss).then(a.pid.cmp(&b.pid))),
+        ProcessSort::Pid => processes.sort_by_key(|process| process.pid),
+    }
+}
+
+/// Output in the format of `PROCESS_COMMAND` from file reads alone. Costs
+/// three reads per process.
+pub fn read_files(transport: &mut dyn Transport) -> Result<String, MonitorError> {
+    let uptime = transport.read_file("/proc/uptime")?;
+    let mut pids = transport
+        .list_dir("/proc")?
+        .into_iter()
+        .filter(|name| name.bytes().all(|b| b.is_ascii_digit()))
+        .collect::<Vec<_>>();
+    pids.sort();
+
+    let mut stat = String::new();
+    let mut status = String::new();
+    let mut cmdline = String::new();
+    // Processes that exit while being read are skipped.
+    for pid in pids {
+        let Ok(pid_stat) = transport.read_file(&format!("/proc/{}/stat", pid)) else {
+            continue;
+        };
+        for line in pid_stat.lines() {
+            let _ = writeln!(stat, "{}/stat:{}", pid, line);
+        }
+        for line in transport
+            .read_file(&format!("/proc/{}/status", pid))
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| line.starts_with("Uid:") || line.starts_with("VmRSS:"))
+        {
+            let _ = writeln!(status, "{}/status:{}", pid, line);
+        }
+        let args = transport
+            .read_file(&format!("/proc/{}/cmdline", pid))
+            .unwrap_or_default();
+        let args = args
+            .chars()
+            .take(512)
+            .collect::<String>()
+            .replace(['\0', '\n'], " ");
+        let _ = writeln!(cmdline, "{}/cmdline:{}", pid, args);
+    }
+    let passwd = transport.read_file("/etc/passwd").unwrap_or_default();
+    Ok(format!(
+        "{}{}\n@@stat\n{}@@status\n{}@@cmdline\n{}@@passwd\n{}",
+        uptime, CLOCK_TICKS, stat, status, cmdline, passwd
+    ))
+}
+
+/// Joins `grep -H` output into file contents by pid, a space for each newline.
+fn prefixed_files(lines: &[&str]) -> HashMap<u32, String> {
+    let mut files: HashMap<u32, String> = HashMap::new();
+    for line in lines {
+        // 1234/cmdline:sleep 30
+        let Some((path, content)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(pid) = pid_of(path) else {
+            continue;
+        };
+        let file = files.entry(pid).or_default();
+        if !file.is_empty() {
+            file.push(' ');
+        }
+        file.push_str(content);
+    }
+    files
+}
+
+/// `1234/stat` -> 1234
+fn pid_of(path: &str) -> Option<u32> {
+    path.split('/').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell;
+    use std::process::Command;
+
+    /// An argument that would add a section of the sample script and a passwd
+    /// entry if its newlines were kept.
+    const HOSTILE: &str =
+        "x\n@@@rsshtop section meminfo\nMemTotal: 1 kB\n@@passwd\nroot:x:4242:0::/:/bin/sh";
+
+    fn markers(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| line.starts_with("@@"))
+            .collect()
+    }
+
+    /// Files by path, standing in for SFTP.
+    struct Files(HashMap<String, String>);
+
+    impl Transport for Files {
+        fn run(&mut self, _script: &str) -> Result<String, MonitorError> {
+            unreachable!()
+        }
+
+        fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+            self.0.get(path).cloned().ok_or_else(|| MonitorError::Read {
+                path: path.to_string(),
+                message: "No such file".to_string(),
+            })
+        }
+
+        fn list_dir(&mut self, _path: &str) -> Result<Vec<String>, MonitorError> {
+            Ok(vec!["7".to_string()])
+        }
+    }
+
+    #[test]
+    fn command_keeps_hostile_arguments_on_one_line() {
+        // `; :` keeps sh from exec'ing sleep, so HOSTILE stays its $0.
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30; :", HOSTILE])
+            .spawn()
+            .unwrap();
+        let cmdline = format!("/proc/{}/cmdline", child.id());
+        while !std::fs::read_to_string(&cmdline)
+            .unwrap_or_default()
+            .contains("@@@rsshtop")
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let output = shell::run_local(PROCESS_COMMAND);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let output = output.unwrap();
+        assert_eq!(
+            markers(&output),
+            ["@@stat", "@@status", "@@cmdline", "@@passwd"]
+        );
+        let (processes, _) = parse(&output, &ProcessTicks::default());
+        let process = processes
+            .iter()
+            .find(|process| process.pid == child.id())
+            .unwrap();
+        assert!(process
+            .command
+            .contains("@@@rsshtop section meminfo MemTotal: 1 kB"));
+    }
+
+    #[test]
+    fn read_files_keeps_hostile_arguments_on_one_line() {
+        let mut files = Files(HashMap::from([
+            ("/proc/uptime".to_string(), "100.00 300.00\n".to_string()),
+            (
+                "/proc/7/stat".to_string(),
+                "7 (sh) S 1 7 7 0 -1 4194304 0 0 0 0 1 2 0 0 20 0 1 0 50 2580480 220".to_string(),
+            ),
+            (
+                "/proc/7/status".to_string(),
+                "Uid:\t1000\t1000\t1000\t1000\n".to_string(),
+            ),
+            ("/proc/7/cmdline".to_string(), format!("sh\0{}\0", HOSTILE)),
+            (
+                "/etc/passwd".to_string(),
+                "app:x:1000:1000::/:/bin/sh\n".to_string(),
+            ),
+        ]));
+        let output = read_files(&mut files).unwrap();
+        assert_eq!(
+            markers(&output),
+            ["@@stat", "@@status", "@@cmdline", "@@passwd"]
+        );
+        let (processes, _) = parse(&output, &ProcessTicks::default());
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].user, "app");
+    }
+
+    #[test]
+    fn newline_in_process_name_stays_in_its_entry() {
+        // comm is `a\n@@passwd\nx:x:0`, as `grep -H` prints it.
+        let output = "100.00 300.00\n100\n\
+            @@stat\n\
+            7/stat:7 (a\n\
+            7/stat:@@passwd\n\
+            7/stat:x:x:0) S 1 7 7 0 -1 4194304 0 0 0 0 1 2 0 0 20 0 1 0 50 2580480 220\n\
+            @@status\n\
+            7/status:Uid:\t0\t0\t0\t0\n\
+            @@cmdline\n\
+            @@passwd\n\
+            root:x:0:0::/root:/bin/sh\n";
+        let (processes, _) = parse(output, &ProcessTicks::default());
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].command, "[a @@passwd x:x:0]");
+        assert_eq!(processes[0].state, 'S');
+        assert_eq!(processes[0].user, "root");
+    }
+}
@@ -0,0 +1,595 @@
+use crate::fleet::{Fleet, HostState, HostStatus};
+use crossbeam_channel::{select, tick, Receiver};
+use remote_monitoring::stats::CpuInfo;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Name, type and help text of every metric, in exposition order.
+const FAMILIES: &[(&str, &str, &str)] = &[
+    (
+        "rsshtop_up",
+        "gauge",
+        "1 if the last sample of the host succeeded.",
+    ),
+    (
+        "rsshtop_uptime_seconds",
+        "gauge",
+        "Time since the host booted.",
+    ),
+    ("rsshtop_load1", "gauge", "1-minute load average."),
+    ("rsshtop_load5", "gauge", "5-minute load average."),
+    ("rsshtop_load15", "gauge", "15-minute load average."),
+    ("rsshtop_procs_running", "gauge", "Runnable tasks."),
+    ("rsshtop_procs_total", "gauge", "Tasks in total."),
+    (
+        "rsshtop_cpu_percent",
+        "gauge",
+        "Share of all CPU time spent per mode since the previous sample.",
+    ),
+    (
+        "rsshtop_core_cpu_percent",
+        "gauge",
+        "Share of one core's time spent per mode since the previous sample.",
+    ),
+    ("rsshtop_memory_total_bytes", "gauge", "Physical memory."),
+    (
+        "rsshtop_memory_free_bytes",
+        "gauge",
+        "Unused physical memory.",
+    ),
+    (
+        "rsshtop_memory_buffers_bytes",
+        "gauge",
+        "Memory used for block device buffers.",
+    ),
+    (
+        "rsshtop_memory_cached_bytes",
+        "gauge",
+        "Memory used for the page cache.",
+    ),
+    (
+        "rsshtop_memory_available_bytes",
+        "gauge",
+        "Memory available for new allocations without swapping.",
+    ),
+    (
+        "rsshtop_memory_shared_bytes",
+        "gauge",
+        "Memory used by tmpfs and shared memory.",
+    ),
+    ("rsshtop_memory_slab_bytes", "gauge", "Kernel slab memory."),
+    (
+        "rsshtop_memory_slab_reclaimable_bytes",
+        "gauge",
+        "Slab memory that can be reclaimed.",
+    ),
+    (
+        "rsshtop_memory_dirty_bytes",
+        "gauge",
+        "Memory waiting to be written back to disk.",
+    ),
+    (
+        "rsshtop_memory_writeback_bytes",
+        "gauge",
+        "Memory being written back to disk.",
+    ),
+    (
+        "rsshtop_memory_committed_bytes",
+        "gauge",
+        "Memory the current allocations would need if all were touched.",
+    ),
+    (
+        "rsshtop_memory_commit_limit_bytes",
+        "gauge",
+        "Allocation limit under strict overcommit.",
+    ),
+    (
+        "rsshtop_memory_hugepages_total_bytes",
+        "gauge",
+        "Size of the huge page pool.",
+    ),
+    (
+        "rsshtop_memory_hugepages_free_bytes",
+        "gauge",
+        "Unallocated huge pages.",
+    ),
+    ("rsshtop_swap_total_bytes", "gauge", "Swap space."),
+    ("rsshtop_swap_free_bytes", "gauge", "Unused swap space."),
+    ("rsshtop_filesystem_size_bytes", "gauge", "Filesystem size."),
+    (
+        "rsshtop_filesystem_free_bytes",
+        "gauge",
+        "Filesystem space available to unprivileged users.",
+    ),
+    (
+        "rsshtop_filesystem_used_bytes",
+        "gauge",
+        "Filesystem space in use.",
+    ),
+    (
+        "rsshtop_filesystem_inodes",
+        "gauge",
+        "Filesystem inodes, 0 if the filesystem has no fixed inode table.",
+    ),
+    (
+        "rsshtop_filesystem_inodes_used",
+        "gauge",
+        "Filesystem inodes in use.",
+    ),
+    (
+        "rsshtop_disk_read_bytes_per_second",
+        "gauge",
+        "Bytes read since the previous sample, per second.",
+    ),
+    (
+        "rsshtop_disk_written_bytes_per_second",
+        "gauge",
+        "Bytes written since the previous sample, per second.",
+    ),
+    (
+        "rsshtop_disk_reads_per_second",
+        "gauge",
+        "Completed reads since the previous sample, per second.",
+    ),
+    (
+        "rsshtop_disk_writes_per_second",
+        "gauge",
+        "Completed writes since the previous sample, per second.",
+    ),
+    (
+        "rsshtop_disk_await_seconds",
+        "gauge",
+        "Average time a request took since the previous sample.",
+    ),
+    (
+        "rsshtop_disk_utilization_percent",
+        "gauge",
+        "Share of the time since the previous sample the disk was busy.",
+    ),
+    (
+        "rsshtop_network_receive_bytes_total",
+        "counter",
+        "Bytes received by the interface.",
+    ),
+    (
+        "rsshtop_network_transmit_bytes_total",
+        "counter",
+        "Bytes transmitted by the interface.",
+    ),
+    (
+        "rsshtop_network_receive_packets_total",
+        "counter",
+        "Packets received by the interface.",
+    ),
+    (
+        "rsshtop_network_transmit_packets_total",
+        "counter",
+        "Packets transmitted by the interface.",
+    ),
+];
+
+struct Metric {
+    name: &'static str,
+    labels: Vec<(&'static str, String)>,
+    value: f64,
+}
+
+/// How long a client may take to send its request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Polls every host on the interval and serves `/metrics` on `listener` until Ctrl-C.
+pub fn serve(fleet: Fleet, listener: TcpListener, interval: usize, ctrl_c_events: Receiver<()>) {
+    let states = fleet.states.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Each connection gets its own thread, so a client that never sends its
+            // request holds up only itself.
+            let states = states.clone();
+            thread::spawn(move || {
+                // A scrape that fails halfway is retried by Prometheus on the next interval.
+                let _ = respond(stream, &states);
+            });
+        }
+    });
+
+    let ticks = tick(Duration::from_secs(interval as u64));
+    fleet.tick();
+    loop {
+        select! {
+            recv(ticks) -> _ => fleet.tick(),
+            recv(fleet.updates) -> _ => {}
+            recv(ctrl_c_events) -> _ => {
+                println!("Goodbye!");
+                break;
+            }
+        }
+    }
+}
+
+fn respond(stream: TcpStream, states: &[Arc<Mutex<HostState>>]) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are not needed, but have to be read before answering.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(states),
+        ),
+        (Some("GET"), Some("/")) => (
+            "200 OK",
+            "text/html; charset=utf-8",
+            String::from("<a href=\"/metrics\">Metrics</a>\n"),
+        ),
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            String::from("Not found\n"),
+        ),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The text exposition format of every host's latest sample. Hosts whose session is
+/// down, or that have only the baseline sample so far, report just `rsshtop_up`.
+fn render(states: &[Arc<Mutex<HostState>>]) -> String {
+    let mut metrics = Vec::new();
+    for state in states {
+        collect(&state.lock().unwrap(), &mut metrics);
+    }
+
+    let mut out = String::new();
+    for (name, kind, help) in FAMILIES {
+        let mut family = metrics
+            .iter()
+            .filter(|metric| metric.name == *name)
+            .peekable();
+        if family.peek().is_none() {
+            continue;
+        }
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for metric in family {
+            let labels = metric
+                .labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = writeln!(out, "{}{{{}}} {}", metric.name, labels, metric.value);
+        }
+    }
+    out
+}
+
+fn collect(state: &HostState, metrics: &mut Vec<Metric>) {
+    let host = state.name.clone();
+    let mut push = |name: &'static str, mut labels: Vec<(&'static str, String)>, value: f64| {
+        labels.insert(0, ("host", host.clone()));
+        metrics.push(Metric {
+            name,
+            labels,
+            value,
+        });
+    };
+
+    let stats = match (&state.status, &state.stats) {
+        (HostStatus::Up, Some(stats)) if state.samples >= 2 => stats,
+        (HostStatus::Up, _) => {
+            push("rsshtop_up", vec![], 1.0);
+            return;
+        }
+        _ => {
+            push("rsshtop_up", vec![], 0.0);
+            return;
+        }
+    };
+    push("rsshtop_up", vec![], 1.0);
+    push("rsshtop_uptime_seconds", vec![], stats.uptime);
+    push("rsshtop_load1", vec![], stats.load1.parse().unwrap_or(0.0));
+    push("rsshtop_load5", vec![], stats.load5.parse().unwrap_or(0.0));
+    push(
+        "rsshtop_load15",
+        vec![],
+        stats.load10.parse().unwrap_or(0.0),
+    );
+    push(
+        "rsshtop_procs_running",
+        vec![],
+        stats.running_procs.trim().parse().unwrap_or(0.0),
+    );
+    push(
+        "rsshtop_procs_total",
+        vec![],
+        stats.total_procs.trim().parse().unwrap_or(0.0),
+    );
+
+    for (mode, value) in cpu_modes(&stats.cpu) {
+        push(
+            "rsshtop_cpu_percent",
+            vec![("mode", mode.to_string())],
+            value,
+        );
+    }
+    for (core, cpu) in &stats.cores {
+        for (mode, value) in cpu_modes(cpu) {
+            push(
+                "rsshtop_core_cpu_percent",
+                vec![("cpu", core.to_string()), ("mode", mode.to_string())],
+                value,
+            );
+        }
+    }
+
+    let memory = &stats.memory;
+    for (name, value) in [
+        ("rsshtop_memory_total_bytes", memory.total()),
+        ("rsshtop_memory_free_bytes", memory.free()),
+        ("rsshtop_memory_available_bytes", memory.available()),
+        ("rsshtop_memory_buffers_bytes", memory.buffers()),
+        ("rsshtop_memory_cached_bytes", memory.cached()),
+        ("rsshtop_memory_shared_bytes", memory.shared()),
+        ("rsshtop_memory_slab_bytes", memory.slab()),
+        (
+            "rsshtop_memory_slab_reclaimable_bytes",
+            memory.slab_reclaimable(),
+        ),
+        ("rsshtop_memory_dirty_bytes", memory.dirty()),
+        ("rsshtop_memory_writeback_bytes", memory.writeback()),
+        ("rsshtop_memory_committed_bytes", memory.committed()),
+        ("rsshtop_memory_commit_limit_bytes", memory.commit_limit()),
+        (
+            "rsshtop_memory_hugepages_total_bytes",
+            memory.huge_pages_total(),
+        ),
+        (
+            "rsshtop_memory_hugepages_free_bytes",
+            memory.huge_pages_free(),
+        ),
+        ("rsshtop_swap_total_bytes", memory.swap_total()),
+        ("rsshtop_swap_free_bytes", memory.swap_free()),
+    ] {
+        push(name, vec![], value as f64);
+    }
+
+    for fs in &stats.fs_infos {
+        let labels = || {
+            vec![
+                ("mountpoint", fs.mount_point.clone()),
+                ("device", fs.device.clone()),
+                ("fstype", fs.fs_type.clone()),
+            ]
+        };
+        push("rsshtop_filesystem_size_bytes", labels(), fs.size as f64);
+        push("rsshtop_filesystem_free_bytes", labels(), fs.free as f64);
+        push("rsshtop_filesystem_used_bytes", labels(), fs.used as f64);
+        push("rsshtop_filesystem_inodes", labels(), fs.inodes as f64);
+        push(
+            "rsshtop_filesystem_inodes_used",
+            labels(),
+            fs.inodes_used as f64,
+        );
+    }
+
+    for disk in &stats.disks {
+        let labels = || vec![("device", disk.name.clone())];
+        push(
+            "rsshtop_disk_read_bytes_per_second",
+            labels(),
+            disk.read_rate,
+        );
+        push(
+            "rsshtop_disk_written_bytes_per_second",
+            labels(),
+            disk.write_rate,
+        );
+        push("rsshtop_disk_reads_per_second", labels(), disk.read_iops);
+        push("rsshtop_disk_writes_per_second", labels(), disk.write_iops);
+        push(
+            "rsshtop_disk_await_seconds",
+            labels(),
+            disk.await_ms / 1000.0,
+        );
+        push(
+            "rsshtop_disk_utilization_percent",
+            labels(),
+            disk.util as f64,
+        );
+    }
+
+    let mut interfaces = stats.net_intf.iter().collect::<Vec<_>>();
+    interfaces.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, intf) in interfaces {
+        let labels = || vec![("interface", name.clone())];
+        push(
+            "rsshtop_network_receive_bytes_total",
+            labels(),
+            intf.rx as f64,
+        );
+        push(
+            "rsshtop_network_transmit_bytes_total",
+            labels(),
+            intf.tx as f64,
+        );
+        push(
+            "rsshtop_network_receive_packets_total",
+            labels(),
+            intf.rx_packets as f64,
+        );
+        push(
+            "rsshtop_network_transmit_packets_total",
+            labels(),
+            intf.tx_packets as f64,
+        );
+    }
+}
+
+fn cpu_modes(cpu: &CpuInfo) -> [(&'static str, f64); 9] {
+    [
+        ("user", cpu.user as f64),
+        ("nice", cpu.nice as f64),
+        ("system", cpu.system as f64),
+        ("idle", cpu.idle as f64),
+        ("iowait", cpu.iowait as f64),
+        ("irq", cpu.irq as f64),
+        ("softirq", cpu.soft_irq as f64),
+        ("steal", cpu.steal as f64),
+        ("guest", cpu.guest as f64),
+    ]
+}
+
+/// Label values escape backslash, double quote and newline.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use remote_monitoring::filesystems::FileSystemInfo;
+    use remote_monitoring::stats::{NetIntfInfo, Stats};
+    use std::io::Read;
+
+    fn host(name: &str, samples: usize) -> Arc<Mutex<HostState>> {
+        let mut stats = Stats {
+            load1: "0.50".to_string(),
+            ..Stats::default()
+        };
+        stats.cpu.iowait = 12.5;
+        stats.fs_infos.push(FileSystemInfo {
+            device: "/dev/sda1".to_string(),
+            fs_type: "ext4".to_string(),
+            mount_point: "/srv/\"a\\b\"\nc".to_string(),
+            size: 1000,
+            used: 400,
+            free: 600,
+            used_percent: 40.0,
+            inodes: 10,
+            inodes_used: 1,
+        });
+        stats.net_intf.insert(
+            "eth0".to_string(),
+            NetIntfInfo {
+                rx: 2048,
+                ..NetIntfInfo::default()
+            },
+        );
+        let mut state = HostState::new(name);
+        for _ in 0..samples {
+            state.collected(&stats);
+        }
+        Arc::new(Mutex::new(state))
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn renders_families_with_labels() {
+        let out = render(&[host("db1", 2)]);
+        assert!(out.starts_with(
+            "# HELP rsshtop_up 1 if the last sample of the host succeeded.\n\
+             # TYPE rsshtop_up gauge\n\
+             rsshtop_up{host=\"db1\"} 1\n"
+        ));
+        assert!(out.contains("rsshtop_load1{host=\"db1\"} 0.5\n"));
+        assert!(out.contains("rsshtop_cpu_percent{host=\"db1\",mode=\"iowait\"} 12.5\n"));
+        assert!(out.contains(
+            "rsshtop_filesystem_used_bytes{host=\"db1\",mountpoint=\"/srv/\\\"a\\\\b\\\"\\nc\",device=\"/dev/sda1\",fstype=\"ext4\"} 400\n"
+        ));
+        assert!(out.contains("# TYPE rsshtop_network_receive_bytes_total counter\n"));
+        assert!(out.contains(
+            "rsshtop_network_receive_bytes_total{host=\"db1\",interface=\"eth0\"} 2048\n"
+        ));
+        // Each family is introduced once.
+        assert_eq!(out.matches("# HELP rsshtop_cpu_percent ").count(), 1);
+    }
+
+    #[test]
+    fn hosts_that_are_down_report_only_up() {
+        let failed = host("db1", 2);
+        failed.lock().unwrap().status = HostStatus::Failed("timeout".to_string());
+        let reconnecting = host("web", 2);
+        reconnecting.lock().unwrap().status = HostStatus::Reconnecting {
+            attempt: 3,
+            error: "refused".to_string(),
+        };
+        // Only the baseline sample so far: up, but nothing to report yet.
+        let baseline = host("cache", 1);
+
+        let out = render(&[failed, reconnecting, baseline]);
+        assert_eq!(
+            out,
+            "# HELP rsshtop_up 1 if the last sample of the host succeeded.\n\
+             # TYPE rsshtop_up gauge\n\
+             rsshtop_up{host=\"db1\"} 0\n\
+             rsshtop_up{host=\"web\"} 0\n\
+             rsshtop_up{host=\"cache\"} 1\n"
+        );
+    }
+
+    fn request(request: &str, states: &[Arc<Mutex<HostState>>]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        respond(stream, states).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_metrics() {
+        let response = request(
+            "GET /metrics HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n",
+            &[host("db1", 2)],
+        );
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(body.contains("rsshtop_up{host=\"db1\"} 1\n"));
+    }
+
+    #[test]
+    fn unknown_paths_are_not_found() {
+        for request_line in ["GET /metricsx HTTP/1.1", "POST /metrics HTTP/1.1", ""] {
+            let response = request(&format!("{}\r\n\r\n", request_line), &[host("db1", 2)]);
+            assert!(
+                response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+                "{}",
+                request_line
+            );
+            assert!(response.ends_with("\r\n\r\nNot found\n"));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use ssh2::{BlockDirections, Channel, Session};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::process::{ChildStderr, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How much of a ProxyCommand's stderr is kept, from the end.
+const STDERR_LIMIT: usize = 4096;
+
+/// Turns a `direct-tcpip` channel into a socket that another `Session` can use
+/// as its transport. The jump session is moved into a pump thread which keeps it
+/// alive for as long as either side of the tunnel is open.
+pub fn bridge_channel(jump_session: Session, channel: Channel) -> io::Result<UnixStream> {
+    let (local, remote) = UnixStream::pair()?;
+    remote.set_nonblocking(true)?;
+    // A blocking read on the channel would hold the session lock and starve writes,
+    // so both directions are served from a single thread that waits for readiness.
+    jump_session.set_blocking(false);
+    thread::spawn(move || pump(jump_session, channel, remote));
+    Ok(local)
+}
+
+fn pump(session: Session, mut channel: Channel, mut socket: UnixStream) {
+    let mut buf = [0u8; 32 * 1024];
+    let mut to_channel: Vec<u8> = Vec::new();
+    let mut to_socket: Vec<u8> = Vec::new();
+
+    loop {
+        let mut idle = true;
+
+        if to_channel.is_empty() {
+            match socket.read(&mut buf) {
+                // The session on the other end is gone.
+                Ok(0) => {
+                    let _ = channel.send_eof();
+                    return;
+                }
+                Ok(n) => to_channel.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => return,
+            }
+        }
+        if !to_channel.is_empty() {
+            match channel.write(&to_channel) {
+                Ok(n) => {
+                    to_channel.drain(..n);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => return,
+            }
+        }
+
+        if to_socket.is_empty() {
+            match channel.read(&mut buf) {
+                Ok(0) if channel.eof() => return,
+                Ok(n) => to_socket.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => return,
+            }
+        }
+        if !to_socket.is_empty() {
+            match socket.write(&to_socket) {
+                Ok(n) => {
+                    to_socket.drain(..n);
+                    idle = false;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(_) => return,
+            }
+        }
+
+        if idle
+            && !wait(
+                &session,
+                &socket,
+                to_channel.is_empty(),
+                !to_socket.is_empty(),
+            )
+        {
+            return;
+        }
+    }
+}
+
+/// Sleeps until the socket or the jump session's connection is ready for what
+/// the pump tried last: more input from the socket, room in it, or SSH traffic
+/// in the directions libssh2 is blocked on. False if waiting itself failed.
+fn wait(session: &Session, socket: &UnixStream, read_socket: bool, write_socket: bool) -> bool {
+    let mut socket_events = 0;
+    if read_socket {
+        socket_events |= libc::POLLIN;
+    }
+    if write_socket {
+        socket_events |= libc::POLLOUT;
+    }
+    let mut session_events = if write_socket { 0 } else { libc::POLLIN };
+    match session.block_directions() {
+        BlockDirections::Inbound => session_events |= libc::POLLIN,
+        BlockDirections::Outbound => session_events |= libc::POLLOUT,
+        BlockDirections::Both => session_events |= libc::POLLIN | libc::POLLOUT,
+        BlockDirections::None => {}
+    }
+    let mut fds = [
+        libc::pollfd {
+            fd: socket.as_raw_fd(),
+            events: socket_events,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: session.as_raw_fd(),
+            events: session_events,
+            revents: 0,
+        },
+    ];
+    // The timeout only bounds how long a missed wakeup could stall the tunnel.
+    let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 1000) };
+    ready >= 0 || io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+}
+
+/// The end of a ProxyCommand's stderr, collected in the background.
+pub struct ProxyStderr {
+    state: Arc<(Mutex<StderrState>, Condvar)>,
+}
+
+#[derive(Default)]
+struct StderrState {
+    output: Vec<u8>,
+    /// The command has closed stderr, usually by exiting.
+    closed: bool,
+}
+
+impl ProxyStderr {
+    fn collect(mut stderr: ChildStderr) -> Self {
+        let state = Arc::new((Mutex::new(StderrState::default()), Condvar::new()));
+        let shared = state.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            // Read until the command exits, so it never blocks on a full pipe.
+            while let Ok(n @ 1..) = stderr.read(&mut buf) {
+                let mut state = shared.0.lock().unwrap();
+                state.output.extend_from_slice(&buf[..n]);
+                let excess = state.output.len().saturating_sub(STDERR_LIMIT);
+                state.output.drain(..excess);
+            }
+            shared.0.lock().unwrap().closed = true;
+            shared.1.notify_all();
+        });
+        ProxyStderr { state }
+    }
+
+    /// What the command printed to stderr, trimmed. Waits up to a second for a
+    /// command that is exiting to close stderr, so its last words are included.
+    pub fn text(&self) -> String {
+        let (lock, closed) = &*self.state;
+        let (state, _) = closed
+            .wait_timeout_while(lock.lock().unwrap(), Duration::from_secs(1), |state| {
+                !state.closed
+            })
+            .unwrap();
+        String::from_utf8_lossy(&state.output).trim().to_string()
+    }
+}
+
+/// Runs `command` through `sh -c` with `%h`, `%p` and `%r` expanded, and uses
+/// its stdin/stdout as the transport, like OpenSSH's ProxyCommand. Its stderr
+/// is kept for error messages.
+pub fn spawn_proxy_command(
+    command: &str,
+    host: &str,
+    port: u16,
+    user: &str,
+) -> io::Result<(UnixStream, ProxyStderr)> {
+    let command = command
+        .replace("%h", host)
+        .replace("%p", &port.to_string())
+        .replace("%r", user)
+        .replace("%%", "%");
+    let (local, remote) = UnixStream::pair()?;
+    let stdin = OwnedFd::from(remote.try_clone()?);
+    let stdout = OwnedFd::from(remote);
+    let mut child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::from(stdin))
+        .stdout(Stdio::from(stdout))
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stderr = ProxyStderr::collect(child.stderr.take().expect("stderr is piped"));
+    thread::spawn(move || child.wait());
+    Ok((local, stderr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_command_stderr_is_kept() {
+        let (mut stream, stderr) = spawn_proxy_command(
+            "echo 'connect to %h port %p: No route to host' >&2; exit 255",
+            "db1",
+            2222,
+            "admin",
+        )
+        .unwrap();
+        let mut stdout = Vec::new();
+        stream.read_to_end(&mut stdout).unwrap();
+        assert!(stdout.is_empty());
+        assert_eq!(stderr.text(), "connect to db1 port 2222: No route to host");
+    }
+
+    #[test]
+    fn proxy_command_stderr_keeps_the_end() {
+        let (_stream, stderr) =
+            spawn_proxy_command("yes x | head -c 100000 >&2; echo last >&2", "h", 22, "u").unwrap();
+        let text = stderr.text();
+        assert!(text.len() <= STDERR_LIMIT);
+        assert!(text.ends_with("x\nlast"));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::output::{Snapshot, SCHEMA_VERSION};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Appends every sample as one NDJSON line in the `--output ndjson` schema.
+/// Shared by the collectors of all hosts.
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn record(&self, snapshot: &Snapshot) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(snapshot)?;
+        line.push('\n');
+        // One write per line keeps lines of concurrent hosts apart.
+        self.file
+            .lock()
+            .unwrap()
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("Failed to record sample: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Reads a recording. Blank lines are skipped, anything else that is not a
+/// snapshot is an error naming the line. A last line without its newline was
+/// cut short by rsshtop stopping mid-write and is left out.
+pub fn load(path: &str) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut snapshots = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let snapshot: Snapshot = match serde_json::from_str(line) {
+            Ok(snapshot) => snapshot,
+            Err(_) if number + 1 == lines.len() && !contents.ends_with('\n') => break,
+            Err(e) => return Err(format!("{} line {}: {}", path, number + 1, e).into()),
+        };
+        if snapshot.schema_version > SCHEMA_VERSION {
+            return Err(format!(
+                "{} line {}: schema version {} is newer than this rsshtop supports ({})",
+                path,
+                number + 1,
+                snapshot.schema_version,
+                SCHEMA_VERSION
+            )
+            .into());
+        }
+        snapshots.push(snapshot);
+    }
+    if snapshots.is_empty() {
+        return Err(format!("{} contains no samples", path).into());
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use remote_monitoring::stats::Stats;
+    use std::env;
+
+    fn snapshot(host: &str, timestamp: &str) -> Snapshot {
+        let mut snapshot = Snapshot::new(host, &Stats::default());
+        snapshot.timestamp = timestamp.to_string();
+        snapshot
+    }
+
+    fn line(snapshot: &Snapshot) -> String {
+        serde_json::to_string(snapshot).unwrap() + "\n"
+    }
+
+    /// The number of samples loaded from `contents`.
+    fn load_str(name: &str, contents: &str) -> Result<usize, String> {
+        let path = env::temp_dir().join(format!("rsshtop-{}-{}.ndjson", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let result = load(path.to_str().unwrap())
+            .map(|snapshots| snapshots.len())
+            .map_err(|e| e.to_string());
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn records_and_loads_several_hosts_in_order() {
+        let path = env::temp_dir().join(format!("rsshtop-record-{}.ndjson", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let recorder = Recorder::create(path.to_str().unwrap()).unwrap();
+        for (host, timestamp) in [
+            ("db1", "2024-05-01T10:00:00Z"),
+            ("web", "2024-05-01T10:00:01Z"),
+            ("db1", "2024-05-01T10:00:05Z"),
+        ] {
+            recorder.record(&snapshot(host, timestamp)).unwrap();
+        }
+        let snapshots = load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let samples = snapshots
+            .iter()
+            .map(|snapshot| (snapshot.host.as_str(), snapshot.timestamp.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [
+                ("db1", "2024-05-01T10:00:00Z"),
+                ("web", "2024-05-01T10:00:01Z"),
+                ("db1", "2024-05-01T10:00:05Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_a_truncated_last_line() {
+        let first = line(&snapshot("db1", "2024-05-01T10:00:00Z"));
+        let second = line(&snapshot("db1", "2024-05-01T10:00:05Z"));
+        let contents = format!("{}\n{}{}", first, second, &second[..40]);
+        assert_eq!(load_str("truncated", &contents), Ok(2));
+
+        // Cut short anywhere else, the recording is broken.
+        let contents = format!("{}\n{}", &first[..40], second);
+        let error = load_str("broken", &contents).unwrap_err();
+        assert!(
+            error.contains(".ndjson line 1: EOF while parsing"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn rejects_newer_schemas_and_empty_recordings() {
+        let mut newer = snapshot("db1", "2024-05-01T10:00:05Z");
+        newer.schema_version = SCHEMA_VERSION + 1;
+        let contents = line(&snapshot("db1", "2024-05-01T10:00:00Z")) + &line(&newer);
+        let error = load_str("newer", &contents).unwrap_err();
+        assert!(
+            error.ends_with(&format!(
+                "line 2: schema version {} is newer than this rsshtop supports ({})",
+                SCHEMA_VERSION + 1,
+                SCHEMA_VERSION
+            )),
+            "{}",
+            error
+        );
+
+        let error = load_str("empty", "\n\n").unwrap_err();
+        assert!(error.ends_with("contains no samples"), "{}", error);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::fleet::{HostState, HostStatus};
+use crate::output::Snapshot;
+use crate::recording;
+use crate::tui;
+use colored::Colorize;
+use crossbeam_channel::{select, tick, Receiver};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+pub const MIN_SPEED: f64 = 0.125;
+pub const MAX_SPEED: f64 = 64.0;
+
+/// Plays a recording back into host states on a virtual clock, so the live renderers
+/// can show it. The clock counts seconds since the first sample.
+pub struct Player {
+    pub states: Vec<Arc<Mutex<HostState>>>,
+    /// Samples sorted by time, with their host's index and their time on the clock.
+    frames: Vec<(usize, f64, Snapshot)>,
+    start: OffsetDateTime,
+    /// Number of frames applied to `states`.
+    position: usize,
+    clock: f64,
+    pub speed: f64,
+    pub paused: bool,
+}
+
+impl Player {
+    pub fn new(snapshots: Vec<Snapshot>, speed: f64) -> Result<Self, Box<dyn Error>> {
+        let mut hosts: Vec<String> = Vec::new();
+        let mut timed = Vec::new();
+        for snapshot in snapshots {
+            let time = OffsetDateTime::parse(&snapshot.timestamp, &Rfc3339)
+                .map_err(|e| format!("invalid timestamp '{}': {}", snapshot.timestamp, e))?;
+            let host = match hosts.iter().position(|host| *host == snapshot.host) {
+                Some(host) => host,
+                None => {
+                    hosts.push(snapshot.host.clone());
+                    hosts.len() - 1
+                }
+            };
+            timed.push((host, time, snapshot));
+        }
+        timed.sort_by_key(|(_, time, _)| *time);
+        let start = timed[0].1;
+
+        let mut player = Player {
+            states: Vec::new(),
+            frames: timed
+                .into_iter()
+                .map(|(host, time, snapshot)| (host, (time - start).as_seconds_f64(), snapshot))
+                .collect(),
+            start,
+            position: 0,
+            clock: 0.0,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            paused: false,
+        };
+        player.states = hosts
+            .iter()
+            .map(|host| Arc::new(Mutex::new(HostState::new(host))))
+            .collect();
+        player.seek_to(0.0);
+        Ok(player)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames.last().map_or(0.0, |frame| frame.1)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.position == self.frames.len()
+    }
+
+    /// Moves the clock on by `elapsed` real seconds times the speed, unless paused.
+    /// Returns whether any host changed.
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        if self.paused {
+            return false;
+        }
+        self.clock = (self.clock + elapsed.as_secs_f64() * self.speed).min(self.duration());
+        self.apply_until_clock()
+    }
+
+    /// Jumps `seconds` forward, or back if negative.
+    pub fn seek(&mut self, seconds: f64) {
+        self.seek_to(self.clock + seconds);
+    }
+
+    pub fn seek_to(&mut self, clock: f64) {
+        let clock = clock.clamp(0.0, self.duration());
+        if clock < self.clock || self.position == 0 {
+            // The histories are rebuilt from the beginning.
+            for state in &self.states {
+                let name = state.lock().unwrap().name.clone();
+                *state.lock().unwrap() = HostState::new(&name);
+            }
+            self.position = 0;
+        }
+        self.clock = clock;
+        self.apply_until_clock();
+    }
+
+    fn apply_until_clock(&mut self) -> bool {
+        let mut changed = false;
+        while let Some((host, time, snapshot)) = self.frames.get(self.position) {
+            if *time > self.clock {
+                break;
+            }
+            let mut state = self.states[*host].lock().unwrap();
+            match (&snapshot.error, snapshot.reconnect_attempt) {
+                (None, _) => state.collected(&snapshot.to_stats()),
+                (Some(error), None) => state.status = HostStatus::Failed(error.clone()),
+                (Some(error), Some(attempt)) => {
+                    state.samples = 0;
+                    state.status = HostStatus::Reconnecting {
+                        attempt,
+                        error: error.clone(),
+                    };
+                }
+            }
+            self.position += 1;
+            changed = true;
+        }
+        changed
+    }
+
+    /// Wall-clock time of the playback position and how far into the recording it is.
+    pub fn position_text(&self) -> String {
+        let now = self.start + time::Duration::seconds_f64(self.clock);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC  {} / {}  x{}",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second(),
+            format_offset(self.clock),
+            format_offset(self.duration()),
+            self.speed
+        )
+    }
+}
+
+fn format_offset(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Loads `path` and plays it in the terminal UI, or prints each sample when `plain`.
+pub fn run(path: &str, speed: f64, plain: bool, ctrl_c_events: Receiver<()>) {
+    let mut player = match recording::load(path).and_then(|snapshots| Player::new(snapshots, speed))
+    {
+        Ok(player) => player,
+        Err(e) => {
+            eprint!("Invalid recording: {}", e);
+            return;
+        }
+    };
+    if !plain {
+        if let Err(e) = tui::run_replay(player) {
+            eprint!("Terminal error: {}", e);
+        }
+        return;
+    }
+
+    // Plain output prints every sample as it is reached, without controls.
+    let mut printed = 0;
+    let ticks = tick(Duration::from_millis(100));
+    let mut last = Instant::now();
+    loop {
+        while printed < player.position {
+            let (_, _, snapshot) = &player.frames[printed];
+            let error = match (&snapshot.error, snapshot.reconnect_attempt) {
+                (None, _) => String::new(),
+                (Some(error), None) => format!("error: {}\n", error),
+                (Some(error), Some(attempt)) => {
+                    format!("reconnecting (attempt {}): {}\n", attempt, error)
+                }
+            };
+            println!(
+                "{}{}{}\n",
+                snapshot.to_stats(),
+                error.bright_red(),
+                format!("{}  recorded {}", snapshot.host, snapshot.timestamp).dimmed()
+            );
+            printed += 1;
+        }
+        if player.finished() {
+            break;
+        }
+        select! {
+            recv(ticks) -> _ => {
+                let now = Instant::now();
+                player.advance(now - last);
+                last = now;
+            }
+            recv(ctrl_c_events) -> _ => {
+                println!("Goodbye!");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use remote_monitoring::stats::Stats;
+
+    fn snapshot(host: &str, second: u32, uptime: f64) -> Snapshot {
+        let stats = Stats {
+            uptime,
+            ..Stats::default()
+        };
+        let mut snapshot = Snapshot::new(host, &stats);
+        snapshot.timestamp = format!("2024-05-01T10:00:{:02}Z", second);
+        snapshot
+    }
+
+    /// db1 every 10 seconds from 0 to 40, web at 5 and 25 and down in between.
+    fn player() -> Player {
+        let mut outage = snapshot("web", 15, 0.0);
+        outage.error = Some("Connection reset".to_string());
+        outage.reconnect_attempt = Some(2);
+        let snapshots = vec![
+            snapshot("db1", 0, 100.0),
+            snapshot("web", 5, 500.0),
+            snapshot("db1", 10, 110.0),
+            outage,
+            snapshot("db1", 20, 120.0),
+            snapshot("web", 25, 525.0),
+            snapshot("db1", 30, 130.0),
+            snapshot("db1", 40, 140.0),
+        ];
+        Player::new(snapshots, 1.0).unwrap()
+    }
+
+    /// Uptime of the latest sample and the number of samples since (re)connecting.
+    fn host(player: &Player, index: usize) -> (Option<f64>, u64) {
+        let state = player.states[index].lock().unwrap();
+        (
+            state.stats.as_ref().map(|stats| stats.uptime),
+            state.samples,
+        )
+    }
+
+    #[test]
+    fn starts_at_the_first_sample() {
+        let player = player();
+        assert_eq!(player.duration(), 40.0);
+        let names = player
+            .states
+            .iter()
+            .map(|state| state.lock().unwrap().name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["db1", "web"]);
+        assert_eq!(host(&player, 0), (Some(100.0), 1));
+        assert_eq!(host(&player, 1), (None, 0));
+        assert!(!player.finished());
+    }
+
+    #[test]
+    fn advances_by_the_speed() {
+        let mut player = player();
+        assert!(!player.advance(Duration::from_secs(4)));
+        assert!(player.advance(Duration::from_secs(1)));
+        assert_eq!(host(&player, 1), (Some(500.0), 1));
+
+        player.speed = 4.0;
+        assert!(player.advance(Duration::from_millis(3750)));
+        assert_eq!(host(&player, 0), (Some(120.0), 3));
+
+        player.paused = true;
+        assert!(!player.advance(Duration::from_secs(60)));
+        assert_eq!(host(&player, 0), (Some(120.0), 3));
+
+        player.paused = false;
+        player.advance(Duration::from_secs(60));
+        assert_eq!(host(&player, 0), (Some(140.0), 5));
+        assert!(player.finished());
+        assert!(player.position_text().contains("00:00:40 / 00:00:40"));
+    }
+
+    #[test]
+    fn seeks_forward_and_back() {
+        let mut player = player();
+        player.seek(30.0);
+        assert_eq!(host(&player, 0), (Some(130.0), 4));
+
+        // Going back rebuilds the states from the start.
+        player.seek(-18.0);
+        assert_eq!(host(&player, 0), (Some(110.0), 2));
+        assert_eq!(host(&player, 1), (Some(500.0), 1));
+
+        player.seek_to(1000.0);
+        assert!(player.finished());
+        player.seek_to(-5.0);
+        assert_eq!(host(&player, 0), (Some(100.0), 1));
+        assert!(!player.finished());
+    }
+
+    #[test]
+    fn outages_are_shown() {
+        let mut player = player();
+        player.seek_to(15.0);
+        {
+            let state = player.states[1].lock().unwrap();
+            let HostStatus::Reconnecting { attempt, error } = &state.status else {
+                panic!("web should be reconnecting");
+            };
+            assert_eq!((*attempt, error.as_str()), (2, "Connection reset"));
+            // The figures from before the outage stay.
+            assert_eq!(state.stats.as_ref().unwrap().uptime, 500.0);
+        }
+        player.seek_to(25.0);
+        assert!(matches!(
+            player.states[1].lock().unwrap().status,
+            HostStatus::Up
+        ));
+    }
+
+    #[test]
+    fn clamps_the_speed() {
+        let snapshots = || vec![snapshot("db1", 0, 100.0), snapshot("db1", 10, 110.0)];
+        assert_eq!(Player::new(snapshots(), 1000.0).unwrap().speed, MAX_SPEED);
+        assert_eq!(Player::new(snapshots(), 0.0).unwrap().speed, MIN_SPEED);
+        assert_eq!(Player::new(snapshots(), 4.0).unwrap().speed, 4.0);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::MonitorError;
+use crate::transport::{read_error, FsUsage, Transport};
+use ssh2::{ErrorCode, Session, Sftp};
+use std::io::Read;
+use std::path::Path;
+
+/// Reads files over the SFTP subsystem, for hosts that allow SFTP but not
+/// running commands. Collectors fall back to reading /proc and /sys.
+pub struct SftpReader {
+    sftp: Sftp,
+    /// Set once the SFTP channel itself failed rather than a single file.
+    broken: bool,
+}
+
+impl SftpReader {
+    pub fn open(session: &Session) -> Result<Self, MonitorError> {
+        let sftp = session
+            .sftp()
+            .map_err(|e| MonitorError::Channel(format!("Failed to start SFTP subsystem: {}", e)))?;
+        Ok(SftpReader {
+            sftp,
+            broken: false,
+        })
+    }
+
+    /// Errors of the channel, rather than SFTP status codes for one file, break it.
+    fn check(&mut self, e: &ssh2::Error) {
+        if let ErrorCode::Session(_) = e.code() {
+            self.broken = true;
+        }
+    }
+}
+
+impl Transport for SftpReader {
+    fn run(&mut self, _script: &str) -> Result<String, MonitorError> {
+        Err(MonitorError::Channel(
+            "commands can't be run over SFTP".to_string(),
+        ))
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+        let mut file = self.sftp.open(Path::new(path)).map_err(|e| {
+            self.check(&e);
+            read_error(path, e)
+        })?;
+        // /proc files report a size of 0, so they are read until EOF.
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).map_err(|e| {
+            self.broken = true;
+            read_error(path, e)
+        })?;
+        Ok(String::from_utf8_lossy(&contents).into_owned())
+    }
+
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError> {
+        let entries = self.sftp.readdir(Path::new(path)).map_err(|e| {
+            self.check(&e);
+            read_error(path, e)
+        })?;
+        Ok(entries
+            .iter()
+            .filter_map(|(path, _)| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .collect())
+    }
+
+    fn fs_usage(&mut self, path: &str) -> Result<FsUsage, MonitorError> {
+        let mut dir = self.sftp.opendir(Path::new(path)).map_err(|e| {
+            self.check(&e);
+            read_error(path, e)
+        })?;
+        let stat = dir
+            .statvfs()
+            .map_err(|e| read_error(path, format!("statvfs: {}", e)))?;
+        Ok(FsUsage {
+            block_size: stat.f_frsize,
+            blocks: stat.f_blocks,
+            blocks_free: stat.f_bfree,
+            blocks_available: stat.f_bavail,
+            inodes: stat.f_files,
+            inodes_free: stat.f_ffree,
+        })
+    }
+
+    fn runs_commands(&self) -> bool {
+        false
+    }
+
+    fn broken(&self) -> bool {
+        self.broken
+    }
+}
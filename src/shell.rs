@@ -0,0 +1,348 @@
+use crate::batch;
+use crate::error::MonitorError;
+use crate::transport::{read_error, Transport};
+use ssh2::{Channel, ExtendedData, Session};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long one command may take before the shell is given up on.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// One `sh` per session that runs the commands written to its stdin, so a
+/// sample costs no channel setup. Each command's output is framed by numbered
+/// begin and end markers with a random tag, so output that happens to look
+/// like a marker isn't taken for one; anything before the begin marker (say,
+/// output of the remote shell's rc files) is dropped.
+pub struct RemoteShell {
+    pipe: Box<dyn ShellPipe>,
+    tag: u64,
+    next_id: u64,
+    command_timeout: Duration,
+    /// Set once the channel has failed. The shell must then be reopened, as its
+    /// output can no longer be matched to commands.
+    broken: bool,
+}
+
+/// The shell's stdin and stdout.
+trait ShellPipe: Send {
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()>;
+    /// Reads what the shell printed, giving up with `TimedOut` after `timeout`.
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize>;
+}
+
+struct SshPipe {
+    session: Session,
+    channel: Channel,
+}
+
+impl ShellPipe for SshPipe {
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.channel.write_all(data)?;
+        self.channel.flush()
+    }
+
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        // Blocking reads give up after the session timeout.
+        let session_timeout = self.session.timeout();
+        self.session
+            .set_timeout(timeout.as_millis().clamp(1, u32::MAX as u128) as u32);
+        let result = self.channel.read(buf);
+        self.session.set_timeout(session_timeout);
+        result
+    }
+}
+
+struct LocalPipe {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl ShellPipe for LocalPipe {
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stdin.write_all(data)?;
+        self.stdin.flush()
+    }
+
+    fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let mut fd = libc::pollfd {
+            fd: self.stdout.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+        match unsafe { libc::poll(&mut fd, 1, millis) } {
+            0 => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+            ready if ready < 0 => Err(io::Error::last_os_error()),
+            _ => self.stdout.read(buf),
+        }
+    }
+}
+
+impl Drop for LocalPipe {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl RemoteShell {
+    pub fn open(session: &Session) -> Result<Self, MonitorError> {
+        let mut channel = session
+            .channel_session()
+            .map_err(|e| MonitorError::Channel(format!("Failed to open channel: {}", e)))?;
+        // Commands' stderr comes back in their sections of the sample script. Any
+        // other stderr, left unread, would fill the channel window and stall stdout.
+        channel
+            .handle_extended_data(ExtendedData::Ignore)
+            .and_then(|()| channel.exec("sh"))
+            .map_err(|e| MonitorError::Channel(format!("Failed to start remote shell: {}", e)))?;
+        Ok(RemoteShell::over(Box::new(SshPipe {
+            session: session.clone(),
+            channel,
+        })))
+    }
+
+    /// A shell on this machine rather than over SSH, framed the same way.
+    pub fn local() -> Result<Self, MonitorError> {
+        let mut child = Command::new("sh")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| MonitorError::Channel(format!("Failed to run sh: {}", e)))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            unreachable!("sh was spawned with piped stdin and stdout");
+        };
+        Ok(RemoteShell::over(Box::new(LocalPipe {
+            child,
+            stdin,
+            stdout,
+        })))
+    }
+
+    fn over(pipe: Box<dyn ShellPipe>) -> Self {
+        let mut tag = RandomState::new().build_hasher();
+        tag.write_u32(std::process::id());
+        RemoteShell {
+            pipe,
+            tag: tag.finish(),
+            next_id: 0,
+            command_timeout: COMMAND_TIMEOUT,
+            broken: false,
+        }
+    }
+
+    fn exchange(&mut self, command: &str) -> Result<String, MonitorError> {
+        if self.broken {
+            return Err(MonitorError::Channel("remote shell is broken".to_string()));
+        }
+        self.next_id += 1;
+        let begin = format!("@@@rsshtop {:016x} begin {}\n", self.tag, self.next_id);
+        let end = format!("\n@@@rsshtop {:016x} end {}\n", self.tag, self.next_id);
+        // The command reads /dev/null, stdin belongs to the shell.
+        let framed = format!(
+            "printf '{}'\n{{ {}\n}} </dev/null\nprintf '{}'\n",
+            begin.replace('\n', "\\n"),
+            command,
+            end.replace('\n', "\\n")
+        );
+        self.pipe.write_all(framed.as_bytes()).map_err(|e| {
+            MonitorError::Channel(format!("Failed to write to remote shell: {}", e))
+        })?;
+
+        let deadline = Instant::now() + self.command_timeout;
+        let timed_out = format!(
+            "remote command timed out after {}s",
+            self.command_timeout.as_secs_f64()
+        );
+        let mut output = Vec::new();
+        let mut buf = [0u8; 32 * 1024];
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Err(timed_out);
+            }
+            match self.pipe.read_timeout(&mut buf, remaining) {
+                Ok(0) => break Err("remote shell exited".to_string()),
+                Ok(n) => output.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break Err(timed_out),
+                Err(e) => break Err(format!("Failed to read from remote shell: {}", e)),
+            }
+            if output.ends_with(end.as_bytes()) {
+                break Ok(());
+            }
+        };
+        result.map_err(MonitorError::Channel)?;
+
+        let output = String::from_utf8_lossy(&output);
+        let output = output.strip_suffix(end.as_str()).unwrap_or(&output);
+        let start = output.find(begin.as_str()).map_or(0, |at| at + begin.len());
+        Ok(output[start..].to_string())
+    }
+
+    /// Runs a command reading `path`, failing with its stderr when it exits non-zero.
+    fn read_with(&mut self, command: &str, path: &str) -> Result<String, MonitorError> {
+        let output = self.run(&batch::script(&[("read", command)]))?;
+        let section = batch::split(&output, &["read"]).remove("read");
+        match section {
+            Some(section) if section.status == Some(0) => Ok(section.output),
+            Some(section) => Err(read_error(
+                path,
+                MonitorError::Command {
+                    command: command.to_string(),
+                    status: section.status,
+                    stderr: section.stderr,
+                },
+            )),
+            None => Err(read_error(path, "no output")),
+        }
+    }
+}
+
+impl Transport for RemoteShell {
+    fn run(&mut self, script: &str) -> Result<String, MonitorError> {
+        let result = self.exchange(script);
+        if result.is_err() {
+            self.broken = true;
+        }
+        result
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+        self.read_with(&format!("cat -- {}", quote(path)), path)
+    }
+
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError> {
+        let output = self.read_with(&format!("ls -1A -- {}", quote(path)), path)?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    fn broken(&self) -> bool {
+        self.broken
+    }
+}
+
+/// Single-quotes `text` for sh.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Runs `script` with the local `sh`. The script goes to stdin
+/// rather than `-c`, so it doesn't show up in the process table.
+pub fn run_local(script: &str) -> Result<String, MonitorError> {
+    let mut child = Command::new("sh")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| MonitorError::Channel(format!("Failed to run sh: {}", e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(script.as_bytes())
+            .map_err(|e| MonitorError::Channel(format!("Failed to write to sh: {}", e)))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| MonitorError::Channel(format!("Failed to read from sh: {}", e)))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_scripts_locally() {
+        let output = run_local("echo one\nprintf 'two %s\\n' \"$((1 + 1))\"\n").unwrap();
+        assert_eq!(output, "one\ntwo 2\n");
+    }
+
+    #[test]
+    fn frames_each_command() {
+        let mut shell = RemoteShell::local().unwrap();
+        assert_eq!(shell.run("echo one").unwrap(), "one\n");
+        // Output without a trailing newline, and none at all.
+        assert_eq!(shell.run("printf two").unwrap(), "two");
+        assert_eq!(shell.run("true").unwrap(), "");
+        // Commands read /dev/null rather than the script that follows them.
+        assert_eq!(shell.run("cat; echo after").unwrap(), "after\n");
+        assert_eq!(shell.run("echo three").unwrap(), "three\n");
+        assert!(!shell.broken());
+    }
+
+    #[test]
+    fn output_that_looks_like_a_marker() {
+        let mut shell = RemoteShell::local().unwrap();
+        shell.run("true").unwrap();
+        let tag = format!("{:016x}", shell.tag);
+        // The untagged markers of this command, and the tagged ones of others.
+        let fake = format!(
+            "@@@rsshtop begin 2\n@@@rsshtop end 2\n@@@rsshtop {0} end 1\n@@@rsshtop {0} begin 3\n@@@rsshtop {0} end 3\n",
+            tag
+        );
+        let command = format!("printf '%s' '{}'", fake);
+        assert_eq!(shell.run(&command).unwrap(), fake);
+        assert_eq!(shell.run("echo next").unwrap(), "next\n");
+    }
+
+    #[test]
+    fn gives_up_on_a_command_that_hangs() {
+        let mut shell = RemoteShell::local().unwrap();
+        shell.command_timeout = Duration::from_millis(200);
+        let started = Instant::now();
+        let error = shell.run("sleep 5").unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(
+            error,
+            MonitorError::Channel("remote command timed out after 0.2s".to_string())
+        );
+        // The late output would be taken for the next command's, so the shell is done.
+        assert!(shell.broken());
+        assert_eq!(
+            shell.run("echo again"),
+            Err(MonitorError::Channel("remote shell is broken".to_string()))
+        );
+    }
+
+    #[test]
+    fn notices_the_shell_exiting() {
+        let mut shell = RemoteShell::local().unwrap();
+        assert_eq!(
+            shell.run("exit 3"),
+            Err(MonitorError::Channel("remote shell exited".to_string()))
+        );
+        assert!(shell.broken());
+    }
+
+    #[test]
+    fn reads_files() {
+        let mut shell = RemoteShell::local().unwrap();
+        let dir = std::env::temp_dir().join(format!("rsshtop-shell-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("it's here"), "contents\n").unwrap();
+        let path = dir.join("it's here");
+        assert_eq!(
+            shell.read_file(path.to_str().unwrap()).unwrap(),
+            "contents\n"
+        );
+        assert_eq!(
+            shell.list_dir(dir.to_str().unwrap()).unwrap(),
+            ["it's here"]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let Err(MonitorError::Read { path, message }) = shell.read_file(dir.to_str().unwrap())
+        else {
+            panic!("reading a missing file should fail");
+        };
+        assert_eq!(path, dir.to_str().unwrap());
+        assert!(message.contains("No such file"), "{}", message);
+        // A command that fails doesn't break the shell.
+        assert!(!shell.broken());
+    }
+}
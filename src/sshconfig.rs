@@ -0,0 +1,430 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings that apply to one host, resolved from an OpenSSH client config.
+/// Only the options rsshtop understands are kept, `Match` blocks are ignored.
+#[derive(Default, Debug, PartialEq)]
+pub struct HostConfig {
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_files: Vec<String>,
+    pub identities_only: bool,
+    pub proxy_jump: Option<String>,
+    pub proxy_command: Option<String>,
+}
+
+/// How deeply `Include`s may nest, as in OpenSSH.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Reads the config file (default ~/.ssh/config) and resolves `alias` against it.
+/// A missing default file yields an empty config.
+pub fn lookup(alias: &str, path: Option<&str>) -> Result<HostConfig, io::Error> {
+    let ssh_dir = env::var_os("HOME").map(|home| Path::new(&home).join(".ssh"));
+    let path = match (path, &ssh_dir) {
+        (Some(path), _) => PathBuf::from(expand_tilde(path)),
+        (None, Some(ssh_dir)) => ssh_dir.join("config"),
+        (None, None) => return Ok(HostConfig::default()),
+    };
+    // Relative Include paths are taken from ~/.ssh, whichever file names them.
+    let include_dir = ssh_dir
+        .or_else(|| path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    match fs::read_to_string(&path) {
+        Ok(contents) => resolve(&contents, &path, alias, &include_dir),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HostConfig::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Applies OpenSSH semantics: every matching `Host` block is consulted in order
+/// and the first value seen for an option wins, except `IdentityFile` which accumulates.
+/// `Include`d files are read in place, relative paths from `include_dir`.
+/// Like OpenSSH, an invalid `Port` anywhere in the config is an error naming
+/// the file (`path` for `contents`) and line.
+pub fn resolve(
+    contents: &str,
+    path: &Path,
+    alias: &str,
+    include_dir: &Path,
+) -> Result<HostConfig, io::Error> {
+    let mut resolver = Resolver {
+        alias,
+        include_dir,
+        config: HostConfig::default(),
+        identities_only: None,
+    };
+    resolver.read(contents, path, 0)?;
+    let mut config = resolver.config;
+    config.identities_only = resolver.identities_only.unwrap_or(false);
+    // "none" disables the proxy.
+    if config.proxy_jump.as_deref() == Some("none") {
+        config.proxy_jump = None;
+    }
+    if config.proxy_command.as_deref() == Some("none") {
+        config.proxy_command = None;
+    }
+    Ok(config)
+}
+
+struct Resolver<'a> {
+    alias: &'a str,
+    include_dir: &'a Path,
+    config: HostConfig,
+    identities_only: Option<bool>,
+}
+
+impl Resolver<'_> {
+    /// Applies one file. Like OpenSSH, an included file starts outside any
+    /// `Host` block and leaves the block it was included from as it was.
+    fn read(&mut self, contents: &str, path: &Path, depth: usize) -> Result<(), io::Error> {
+        // Options before the first Host line apply to every host.
+        let mut active = true;
+
+        for (number, line) in contents.lines().enumerate() {
+            let Some((keyword, args)) = split_line(line) else {
+                continue;
+            };
+            let keyword = keyword.to_ascii_lowercase();
+            if keyword == "host" {
+                active = host_matches(&args, self.alias);
+                continue;
+            }
+            if keyword == "match" {
+                active = false;
+                continue;
+            }
+            if keyword == "port" && !args.is_empty() && !matches!(args[0].parse::<u16>(), Ok(1..)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{} line {}: invalid Port '{}'",
+                        path.display(),
+                        number + 1,
+                        args[0]
+                    ),
+                ));
+            }
+            if !active || args.is_empty() {
+                continue;
+            }
+            if keyword == "include" {
+                if depth < MAX_INCLUDE_DEPTH {
+                    for path in args
+                        .iter()
+                        .flat_map(|arg| include_paths(arg, self.include_dir))
+                    {
+                        if let Ok(contents) = fs::read_to_string(&path) {
+                            self.read(&contents, &path, depth + 1)?;
+                        }
+                    }
+                }
+                continue;
+            }
+            self.apply(&keyword, args);
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, keyword: &str, args: Vec<String>) {
+        let config = &mut self.config;
+        let value = args[0].clone();
+        match keyword {
+            "hostname" if config.hostname.is_none() => {
+                config.hostname = Some(value.replace("%h", self.alias));
+            }
+            "user" if config.user.is_none() => config.user = Some(value),
+            "port" if config.port.is_none() => config.port = value.parse().ok(),
+            "identityfile" => config.identity_files.push(expand_tilde(&value)),
+            // Whichever of ProxyJump and ProxyCommand comes first wins.
+            "proxyjump" if config.proxy_jump.is_none() && config.proxy_command.is_none() => {
+                config.proxy_jump = Some(value);
+            }
+            "proxycommand" if config.proxy_command.is_none() && config.proxy_jump.is_none() => {
+                config.proxy_command = Some(args.join(" "));
+            }
+            "identitiesonly" if self.identities_only.is_none() => {
+                self.identities_only = Some(value.eq_ignore_ascii_case("yes"));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The files an `Include` argument names, in name order. Wildcards are
+/// expanded in the last path component only.
+fn include_paths(arg: &str, include_dir: &Path) -> Vec<PathBuf> {
+    let path = include_dir.join(expand_tilde(arg));
+    let pattern = match path.file_name() {
+        Some(name) if name.to_string_lossy().contains(['*', '?']) => {
+            name.to_string_lossy().into_owned()
+        }
+        _ => return vec![path],
+    };
+    let dir = path.parent().unwrap_or(include_dir);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| wildcard_match(&pattern, &entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Splits a config line into keyword and arguments. Keyword and arguments may be
+/// separated by whitespace or a single `=`, arguments may be double quoted.
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..end].to_string();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in rest.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    Some((keyword, args))
+}
+
+/// A Host line matches when any pattern matches and no negated pattern does.
+fn host_matches(patterns: &[String], host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        if let Some(negated) = pattern.strip_prefix('!') {
+            if wildcard_match(negated, host) {
+                return false;
+            }
+        } else if wildcard_match(pattern, host) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// Matches `*` (any run of characters) and `?` (one character), case-insensitively.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase().chars().collect::<Vec<_>>();
+    let text = text.to_ascii_lowercase().chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn expand_tilde(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return Path::new(&home).join(rest).to_string_lossy().into_owned();
+        }
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(contents: &str, alias: &str) -> HostConfig {
+        resolve_in(contents, alias, Path::new("/nonexistent")).unwrap()
+    }
+
+    fn resolve_in(contents: &str, alias: &str, include_dir: &Path) -> Result<HostConfig, String> {
+        super::resolve(contents, Path::new("config"), alias, include_dir).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("db*", "db1.corp"));
+        assert!(wildcard_match("*.corp", "db1.corp"));
+        assert!(wildcard_match("*.c*p", "db1.corp"));
+        assert!(!wildcard_match("*.corp", "db1.corp.example"));
+        assert!(wildcard_match("db?", "db1"));
+        assert!(!wildcard_match("db?", "db"));
+        assert!(!wildcard_match("db?", "db12"));
+        assert!(wildcard_match("web-??.*", "web-01.prod"));
+        assert!(wildcard_match("DB*", "db1"));
+        assert!(!wildcard_match("db", "db1"));
+    }
+
+    #[test]
+    fn negated_patterns_exclude_hosts() {
+        let config = "Host *.corp !bastion.corp\n  User admin\nHost *\n  User nobody\n";
+        assert_eq!(resolve(config, "db1.corp").user.as_deref(), Some("admin"));
+        assert_eq!(
+            resolve(config, "bastion.corp").user.as_deref(),
+            Some("nobody")
+        );
+        // A negation alone matches nothing.
+        assert_eq!(resolve("Host !db1\n  Port 2222\n", "web1").port, None);
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let config = "\
+# comment
+IdentityFile /keys/global
+Host db1
+  HostName 10.0.0.5
+  Port=2222
+  IdentityFile /keys/db1
+Host db*
+  HostName %h.corp
+  User dba
+  Port 22
+  IdentityFile /keys/db
+  IdentitiesOnly yes
+Host *
+  User nobody
+  IdentitiesOnly no
+Match host db1
+  User matched
+";
+        assert_eq!(
+            resolve(config, "db1"),
+            HostConfig {
+                hostname: Some("10.0.0.5".to_string()),
+                user: Some("dba".to_string()),
+                port: Some(2222),
+                identity_files: vec![
+                    "/keys/global".to_string(),
+                    "/keys/db1".to_string(),
+                    "/keys/db".to_string()
+                ],
+                identities_only: true,
+                proxy_jump: None,
+                proxy_command: None,
+            }
+        );
+        let db2 = resolve(config, "db2");
+        assert_eq!(db2.hostname.as_deref(), Some("db2.corp"));
+        assert_eq!(db2.port, Some(22));
+        let web = resolve(config, "web");
+        assert_eq!(web.user.as_deref(), Some("nobody"));
+        assert!(!web.identities_only);
+    }
+
+    #[test]
+    fn first_proxy_option_wins() {
+        let config = "Host inner\n  ProxyCommand \"ssh -W %h:%p gw\"\n  ProxyJump bastion\nHost *\n  ProxyJump none\n";
+        let inner = resolve(config, "inner");
+        assert_eq!(inner.proxy_command.as_deref(), Some("ssh -W %h:%p gw"));
+        assert_eq!(inner.proxy_jump, None);
+        assert_eq!(resolve(config, "outer").proxy_jump, None);
+    }
+
+    #[test]
+    fn includes_files_in_place() {
+        let dir = env::temp_dir().join(format!("rsshtop-ssh-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("config.d")).unwrap();
+        fs::write(
+            dir.join("config.d/10-db"),
+            "Host db1\n  HostName 10.0.0.5\n  User dba\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.d/20-all"),
+            "User included\nInclude nested\n",
+        )
+        .unwrap();
+        fs::write(dir.join("nested"), "Port 2200\n").unwrap();
+        fs::write(dir.join("web"), "HostName web.corp\n").unwrap();
+        let config = "\
+Host web*
+  Include web
+  User www
+Host *
+  Include config.d/*
+  Port 22
+";
+
+        let db1 = resolve_in(config, "db1", &dir).unwrap();
+        assert_eq!(db1.hostname.as_deref(), Some("10.0.0.5"));
+        assert_eq!(db1.user.as_deref(), Some("dba"));
+        assert_eq!(db1.port, Some(2200));
+
+        // The including Host block carries on after the included file.
+        let web = resolve_in(config, "web1", &dir).unwrap();
+        assert_eq!(web.hostname.as_deref(), Some("web.corp"));
+        assert_eq!(web.user.as_deref(), Some("www"));
+        assert_eq!(web.port, Some(2200));
+
+        // Includes in blocks that don't match are not read.
+        let other = resolve_in("Host db1\n  Include web\n", "db2", &dir).unwrap();
+        assert_eq!(other.hostname, None);
+
+        // Nor are includes past the depth limit.
+        fs::write(dir.join("loop"), "Include loop\nIdentityFile /keys/loop\n").unwrap();
+        let looped = resolve_in("Include loop\n", "db1", &dir).unwrap();
+        assert_eq!(looped.identity_files.len(), MAX_INCLUDE_DEPTH);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_ports() {
+        for port in ["ssh", "0", "65536", "-1"] {
+            let config = format!("Host db1\n  User dba\n  Port {}\n", port);
+            assert_eq!(
+                resolve_in(&config, "db1", Path::new("/nonexistent")),
+                Err(format!("config line 3: invalid Port '{}'", port))
+            );
+        }
+        // Even in a block for another host, or after a Port that already won.
+        let config = "Host db1\n  Port 2222\nHost web\n  Port 22x\n";
+        assert_eq!(
+            resolve_in(config, "db1", Path::new("/nonexistent")),
+            Err("config line 4: invalid Port '22x'".to_string())
+        );
+
+        // Errors in included files name the included file.
+        let dir = env::temp_dir().join(format!("rsshtop-ssh-port-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("db"), "# db hosts\nPort abc\n").unwrap();
+        let error = resolve_in("Host db1\n  Include db\n", "db1", &dir).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            error,
+            format!("{} line 2: invalid Port 'abc'", dir.join("db").display())
+        );
+    }
+}
@@ -1,34 +1,712 @@
+use crate::alerts::{AlertConfig, Alerts};
+use crate::output::Snapshot;
+use crate::proxy;
+use crate::recording::Recorder;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use clap::ValueEnum;
+use remote_monitoring::collector::{self, default_collectors, Collector};
+use remote_monitoring::error::MonitorError;
+use remote_monitoring::filesystems::FsFilter;
+use remote_monitoring::sftp::SftpReader;
+use remote_monitoring::shell::RemoteShell;
+use remote_monitoring::stats::Stats;
+use remote_monitoring::transport::{Local, Transport};
+use serde::Deserialize;
+use ssh2::{CheckResult, ErrorCode, HashType, HostKeyType, KnownHostFileKind, Session};
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+static PROMPT_LOCK: Mutex<()> = Mutex::new(());
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Upper bound for any single blocking libssh2 call, so a dead link surfaces as an error.
+const SESSION_TIMEOUT_MS: u32 = 30_000;
+/// Seconds without traffic after which a keepalive goes out, so idle sessions
+/// survive NAT and firewall timeouts and a closed connection shows up.
+const KEEPALIVE_INTERVAL: u32 = 15;
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How samples are read once connected.
+#[derive(Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    /// Run the sample script in a remote shell
+    #[default]
+    Exec,
+    /// Read /proc files over the SFTP subsystem, without running commands
+    Sftp,
+}
+
 pub enum ConnectionType {
     Agent,
     PrivateKey,
     Password,
 }
 
-pub struct SshConnection<'a> {
-    pub user: &'a str,
-    pub hostname: &'a str, // host:port
-    pub password: Option<&'a str>,
-    pub private_key_path: Option<&'a str>,
+pub struct SshConnection {
+    /// Label for the host in the fleet overview.
+    pub name: String,
+    pub user: String,
+    pub hostname: String,
+    pub port: u16,
+    pub password: Option<String>,
+    pub private_key_path: Option<String>,
+    pub identity_files: Vec<String>,
+    pub identities_only: bool,
+    pub jump_hosts: Vec<SshConnection>,
+    pub proxy_command: Option<String>,
+    pub fs_filter: FsFilter,
+    /// Show partitions and the loop, ram, zram, optical and device mapper devices in the disk I/O view.
+    pub all_disks: bool,
+    pub transport: TransportKind,
+    /// The machine rsshtop runs on, sampled without SSH.
+    pub local: bool,
     pub interval: usize,
     pub connection_type: ConnectionType,
 }
 
-impl<'a> SshConnection<'a> {
+impl SshConnection {
     pub fn new(
-        user: &'a str,
-        hostname: &'a str,
-        password: Option<&'a str>,
-        private_key_path: Option<&'a str>,
+        user: &str,
+        hostname: &str,
+        port: u16,
+        password: Option<&str>,
+        private_key_path: Option<&str>,
         interval: usize,
         connection_type: ConnectionType,
     ) -> Self {
         SshConnection {
-            user,
-            hostname,
-            password,
-            private_key_path,
+            name: hostname.to_string(),
+            user: user.to_string(),
+            hostname: hostname.to_string(),
+            port,
+            password: password.map(str::to_string),
+            private_key_path: private_key_path.map(str::to_string),
+            identity_files: Vec::new(),
+            identities_only: false,
+            jump_hosts: Vec::new(),
+            proxy_command: None,
+            fs_filter: FsFilter::default(),
+            all_disks: false,
+            transport: TransportKind::Exec,
+            local: false,
             interval,
             connection_type,
         }
     }
+
+    pub fn local(interval: usize) -> Self {
+        let mut connection = SshConnection::new(
+            "",
+            "localhost",
+            0,
+            None,
+            None,
+            interval,
+            ConnectionType::Agent,
+        );
+        connection.local = true;
+        connection
+    }
+
+    /// Resolves `hostname` and tries every resulting address in turn.
+    pub fn connect(&self) -> Result<TcpStream, io::Error> {
+        let addrs = (self.hostname.as_str(), self.port).to_socket_addrs()?;
+        let mut last_error = io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to any address", self.hostname),
+        );
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(tcp) => return Ok(tcp),
+                Err(e) => last_error = io::Error::new(e.kind(), format!("{}: {}", addr, e)),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Authenticates `session` as `user`. Agent connections fall back to the
+    /// IdentityFile entries from ssh config, and skip the agent when IdentitiesOnly is set.
+    pub fn authenticate(&self, session: &Session) -> Result<(), ssh2::Error> {
+        match self.connection_type {
+            ConnectionType::PrivateKey => session.userauth_pubkey_file(
+                &self.user,
+                None,
+                Path::new(self.private_key_path.as_deref().unwrap()),
+                None,
+            ),
+            ConnectionType::Password => {
+                session.userauth_password(&self.user, self.password.as_deref().unwrap())
+            }
+            ConnectionType::Agent => {
+                let mut result = Err(ssh2::Error::new(
+                    ErrorCode::Session(-18), // LIBSSH2_ERROR_AUTHENTICATION_FAILED
+                    "No usable identity for authentication",
+                ));
+                if !self.identities_only || self.identity_files.is_empty() {
+                    result = session.userauth_agent(&self.user);
+                }
+                for identity in self.identity_files.iter().map(Path::new) {
+                    if result.is_ok() {
+                        break;
+                    }
+                    if identity.exists() {
+                        result = session.userauth_pubkey_file(&self.user, None, identity, None);
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+/// Connects to `connection` (through its jump hosts or ProxyCommand, if any),
+/// verifies the host key and authenticates.
+pub fn open_session(
+    connection: &SshConnection,
+    refuse_unknown_hosts: bool,
+) -> Result<Session, MonitorError> {
+    open_session_via(connection, &connection.jump_hosts, refuse_unknown_hosts)
+}
+
+fn open_session_via(
+    connection: &SshConnection,
+    jump_hosts: &[SshConnection],
+    refuse_unknown_hosts: bool,
+) -> Result<Session, MonitorError> {
+    let mut session = Session::new()
+        .map_err(|e| MonitorError::Connect(format!("can't create session: {}", e)))?;
+    session.set_timeout(SESSION_TIMEOUT_MS);
+    session.set_keepalive(false, KEEPALIVE_INTERVAL);
+
+    let mut proxy_stderr = None;
+    if let Some((last_jump, previous_jumps)) = jump_hosts.split_last() {
+        let jump_session = open_session_via(last_jump, previous_jumps, refuse_unknown_hosts)
+            .map_err(|e| {
+                MonitorError::Connect(format!("jump host {}: {}", last_jump.hostname, e))
+            })?;
+        let channel = jump_session
+            .channel_direct_tcpip(&connection.hostname, connection.port, None)
+            .map_err(|e| {
+                MonitorError::Connect(format!(
+                    "{} could not reach {}:{}: {}",
+                    last_jump.hostname, connection.hostname, connection.port, e
+                ))
+            })?;
+        let stream = proxy::bridge_channel(jump_session, channel)
+            .map_err(|e| MonitorError::Connect(format!("jump host bridge: {}", e)))?;
+        session.set_tcp_stream(stream);
+    } else if let Some(command) = connection.proxy_command.as_deref() {
+        let (stream, stderr) = proxy::spawn_proxy_command(
+            command,
+            &connection.hostname,
+            connection.port,
+            &connection.user,
+        )
+        .map_err(|e| MonitorError::Connect(format!("can't run ProxyCommand: {}", e)))?;
+        session.set_tcp_stream(stream);
+        proxy_stderr = Some(stderr);
+    } else {
+        let tcp = connection
+            .connect()
+            .map_err(|e| MonitorError::Connect(e.to_string()))?;
+        session.set_tcp_stream(tcp);
+    }
+
+    session.handshake().map_err(|e| {
+        // A ProxyCommand that failed usually said why.
+        match proxy_stderr.map(|stderr| stderr.text()) {
+            Some(stderr) if !stderr.is_empty() => {
+                MonitorError::Connect(format!("handshake failed: {}; ProxyCommand: {}", e, stderr))
+            }
+            _ => MonitorError::Connect(format!("handshake failed: {}", e)),
+        }
+    })?;
+    verify_host_key(
+        &session,
+        &connection.hostname,
+        connection.port,
+        refuse_unknown_hosts,
+    )
+    .map_err(|e| MonitorError::HostKey(e.to_string()))?;
+    connection
+        .authenticate(&session)
+        .map_err(|e| MonitorError::Auth(e.to_string()))?;
+    Ok(session)
+}
+
+/// Outcome of one tick for a `ManagedSession`.
+pub enum Sample {
+    Collected,
+    /// The session is alive but collecting failed.
+    Failed(String),
+    Reconnecting {
+        attempt: u32,
+        error: String,
+    },
+}
+
+/// How `ManagedSession` opens its transport, `open_transport` unless a test swaps in a local shell.
+type OpenTransport = fn(&Session, TransportKind) -> Result<Box<dyn Transport>, MonitorError>;
+
+/// Keeps the session to one host usable, re-establishing it with exponential
+/// backoff (1s, 2s, 4s, ... up to a minute) after it has been marked dead.
+pub struct ManagedSession {
+    connection: SshConnection,
+    session: Option<Session>,
+    /// Reads the samples, reopened after it broke.
+    transport: Option<Box<dyn Transport>>,
+    open_transport: OpenTransport,
+    collectors: Vec<Box<dyn Collector>>,
+    /// Number of the next reconnect attempt, 0 while connected.
+    attempt: u32,
+    last_error: String,
+    next_attempt: Instant,
+    recorder: Option<Recorder>,
+    /// Why the recorder was dropped, once writing to it failed.
+    recording_error: Option<String>,
+    alerts: Option<Alerts>,
+    /// `on_alert` hooks of the last sample that failed to start.
+    hook_errors: Vec<String>,
+    /// Samples since the session was (re)connected, the first only sets baselines.
+    collected: u64,
+}
+
+impl ManagedSession {
+    /// `session` is the result of the first connection attempt, a failure is retried later.
+    pub fn new(connection: SshConnection, session: Result<Session, String>) -> Self {
+        let mut managed = ManagedSession::local(connection);
+        match session {
+            Ok(session) => managed.session = Some(session),
+            Err(e) => managed.failed(e),
+        }
+        managed
+    }
+
+    /// For a `local` connection, which has no session to keep.
+    pub fn local(connection: SshConnection) -> Self {
+        ManagedSession {
+            connection,
+            session: None,
+            transport: None,
+            open_transport,
+            collectors: default_collectors(),
+            attempt: 0,
+            last_error: String::new(),
+            next_attempt: Instant::now(),
+            recorder: None,
+            recording_error: None,
+            alerts: None,
+            hook_errors: Vec::new(),
+            collected: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.connection.name
+    }
+
+    pub fn connection(&self) -> &SshConnection {
+        &self.connection
+    }
+
+    /// Writes every sample after the baseline to `recorder`.
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Evaluates `config`'s rules against every sample after the baseline.
+    pub fn set_alerts(&mut self, config: Arc<AlertConfig>) {
+        self.alerts = Some(Alerts::new(config));
+    }
+
+    /// Names of the alert rules currently firing for this host.
+    pub fn firing_alerts(&self) -> Vec<String> {
+        self.alerts.as_ref().map_or(Vec::new(), Alerts::firing)
+    }
+
+    /// Problems besides collecting: a recording that stopped and alert hooks
+    /// that failed to start. They don't fail the sample.
+    pub fn warnings(&self) -> Vec<String> {
+        self.recording_error
+            .iter()
+            .chain(&self.hook_errors)
+            .cloned()
+            .collect()
+    }
+
+    /// Collects `stats` over the session, reconnecting first if the session was
+    /// lost and its backoff delay has passed. Samples that fail are recorded with
+    /// their error, so a replay shows the host down rather than a gap.
+    pub fn sample(&mut self, stats: &mut Stats) -> Sample {
+        let sample = self.take_sample(stats);
+        let (error, attempt) = match &sample {
+            Sample::Collected => return sample,
+            Sample::Failed(error) => (error, None),
+            Sample::Reconnecting { attempt, error } => (error, Some(*attempt)),
+        };
+        let mut snapshot = Snapshot::new(&self.connection.name, stats);
+        snapshot.error = Some(error.clone());
+        snapshot.reconnect_attempt = attempt;
+        self.record(&snapshot);
+        sample
+    }
+
+    fn take_sample(&mut self, stats: &mut Stats) -> Sample {
+        if self.connection.local {
+            let result = collector::collect(&mut self.collectors, &mut Local, stats);
+            return self.collected(stats, result);
+        }
+        if self.session.is_none() && Instant::now() >= self.next_attempt {
+            // The display owns the terminal by now, so an unknown or changed host
+            // key is the reconnect's error rather than a question.
+            match open_session(&self.connection, true) {
+                Ok(session) => {
+                    self.session = Some(session);
+                    self.attempt = 0;
+                }
+                Err(e) => self.failed(e.to_string()),
+            }
+        }
+        let Some(session) = &self.session else {
+            return Sample::Reconnecting {
+                attempt: self.attempt,
+                error: self.last_error.clone(),
+            };
+        };
+
+        let collectors = &mut self.collectors;
+        let mut opening_failed = false;
+        let result = match &mut self.transport {
+            Some(transport) => collector::collect(collectors, transport.as_mut(), stats),
+            None => match (self.open_transport)(session, self.connection.transport) {
+                Ok(transport) => {
+                    collector::collect(collectors, self.transport.insert(transport).as_mut(), stats)
+                }
+                Err(e) => {
+                    opening_failed = true;
+                    Err(e)
+                }
+            },
+        };
+        if self
+            .transport
+            .as_ref()
+            .is_some_and(|transport| transport.broken())
+        {
+            self.transport = None;
+        }
+        match result {
+            Err(e) if session_lost(session, opening_failed) => {
+                self.session = None;
+                self.transport = None;
+                self.collected = 0;
+                self.failed(e.to_string());
+                stats.reset_baselines();
+                Sample::Reconnecting {
+                    attempt: self.attempt,
+                    error: self.last_error.clone(),
+                }
+            }
+            result => self.collected(stats, result),
+        }
+    }
+
+    /// Filters a sample read without losing the session, then records it and
+    /// evaluates the alerts.
+    fn collected(&mut self, stats: &mut Stats, result: Result<(), MonitorError>) -> Sample {
+        if let Err(e) = result {
+            return Sample::Failed(e.to_string());
+        }
+        let filter = &self.connection.fs_filter;
+        stats.fs_infos.retain(|fs| filter.matches(fs));
+        if !self.connection.all_disks {
+            stats.disks.retain(|disk| disk.shown_by_default());
+        }
+        self.collected += 1;
+        if self.collected < 2 {
+            return Sample::Collected;
+        }
+        self.after_sample(stats);
+        Sample::Collected
+    }
+
+    /// Records the sample and evaluates the alert rules. A recording that can't
+    /// be written is given up on; every alert is notified whatever the others did.
+    fn after_sample(&mut self, stats: &Stats) {
+        self.record(&Snapshot::new(&self.connection.name, stats));
+        if let Some(alerts) = &mut self.alerts {
+            self.hook_errors = alerts
+                .evaluate(&self.connection.name, stats)
+                .iter()
+                .filter_map(|alert| alerts.notify(alert).err())
+                .map(|e| e.to_string())
+                .collect();
+        }
+    }
+
+    fn record(&mut self, snapshot: &Snapshot) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.record(snapshot) {
+                self.recorder = None;
+                self.recording_error = Some(format!("{}, recording stopped", e));
+            }
+        }
+    }
+
+    fn failed(&mut self, error: String) {
+        self.attempt += 1;
+        self.last_error = error;
+        let backoff = Duration::from_secs(1 << (self.attempt - 1).min(6)).min(MAX_BACKOFF);
+        self.next_attempt = Instant::now() + backoff;
+    }
+}
+
+/// Whether a sample failed because the connection is gone rather than a command
+/// or channel on it. A timeout only counts while opening a channel: a command
+/// that hangs times out too, but then a new shell still opens on the session.
+fn session_lost(session: &Session, opening_failed: bool) -> bool {
+    let lost = ssh2::Error::last_session_error(session).is_some_and(|e| match e.code() {
+        ErrorCode::Session(
+            -1 // LIBSSH2_ERROR_SOCKET_NONE
+            | -7 // LIBSSH2_ERROR_SOCKET_SEND
+            | -13 // LIBSSH2_ERROR_SOCKET_DISCONNECT
+            | -30 // LIBSSH2_ERROR_SOCKET_TIMEOUT
+            | -43, // LIBSSH2_ERROR_SOCKET_RECV
+        ) => true,
+        ErrorCode::Session(-9) => opening_failed, // LIBSSH2_ERROR_TIMEOUT
+        _ => false,
+    });
+    // Sends only when the session has been quiet for the keepalive interval.
+    lost || session.keepalive_send().is_err()
+}
+
+/// Opens the transport samples are read over.
+fn open_transport(
+    session: &Session,
+    transport: TransportKind,
+) -> Result<Box<dyn Transport>, MonitorError> {
+    Ok(match transport {
+        TransportKind::Exec => Box::new(RemoteShell::open(session)?),
+        TransportKind::Sftp => Box::new(SftpReader::open(session)?),
+    })
+}
+
+/// Checks the server's host key against ~/.ssh/known_hosts.
+/// Unknown hosts are either refused or confirmed interactively (trust on first use)
+/// and then appended to the file. A changed key is always an error.
+pub fn verify_host_key(
+    session: &Session,
+    host: &str,
+    port: u16,
+    refuse_unknown: bool,
+) -> Result<(), Box<dyn Error>> {
+    let (key, key_type) = session.host_key().ok_or("Server did not send a host key")?;
+    // Hosts of an inventory connect in parallel. One at a time reads the file,
+    // asks and appends, so each sees the keys accepted before it.
+    let _prompt = PROMPT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut known_hosts = session.known_hosts()?;
+    let path = known_hosts_path()?;
+    if path.exists() {
+        known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)?;
+    }
+
+    let fingerprint = match session.host_key_hash(HashType::Sha256) {
+        Some(hash) => format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)),
+        None => String::from("(unavailable)"),
+    };
+
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(format!(
+            "REMOTE HOST IDENTIFICATION HAS CHANGED for {}! {} key fingerprint is {}. \
+             Remove the old entry from {} if this change is expected.",
+            host_entry(host, port),
+            key_type_name(key_type),
+            fingerprint,
+            path.display()
+        )
+        .into()),
+        CheckResult::NotFound => {
+            if refuse_unknown {
+                return Err(format!(
+                    "No {} host key is known for {}; connect to it once without \
+                     --refuse-unknown-hosts to confirm it",
+                    key_type_name(key_type),
+                    host_entry(host, port)
+                )
+                .into());
+            }
+            println!(
+                "The authenticity of host '{}' can't be established.\n{} key fingerprint is {}.",
+                host_entry(host, port),
+                key_type_name(key_type),
+                fingerprint
+            );
+            print!("Are you sure you want to continue connecting (yes/no)? ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if answer.trim() != "yes" {
+                return Err("Host key verification failed".into());
+            }
+
+            append_known_host(&path, &host_entry(host, port), key)?;
+            println!(
+                "Permanently added '{}' to the list of known hosts.",
+                host_entry(host, port)
+            );
+            Ok(())
+        }
+        CheckResult::Failure => Err("Failed to check the host key against known_hosts".into()),
+    }
+}
+
+/// Appends one `host keytype base64` line, leaving the rest of the file as it is.
+fn append_known_host(path: &Path, host: &str, key: &[u8]) -> Result<(), Box<dyn Error>> {
+    // The key blob starts with its type name, as a length-prefixed string.
+    let key_type = key
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .and_then(|len| key.get(4..4 + len))
+        .and_then(|name| std::str::from_utf8(name).ok())
+        .ok_or("Malformed host key")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = format!("{} {} {}\n", host, key_type, STANDARD.encode(key));
+    // Start on a line of our own if the file lacks its last newline.
+    if fs::read(path)?.last().is_some_and(|byte| *byte != b'\n') {
+        line.insert(0, '\n');
+    }
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn known_hosts_path() -> Result<PathBuf, Box<dyn Error>> {
+    let home = env::var_os("HOME").ok_or("HOME is not set, cannot locate known_hosts")?;
+    Ok(PathBuf::from(home).join(".ssh").join("known_hosts"))
+}
+
+/// known_hosts uses the bare host name for port 22 and `[host]:port` otherwise.
+fn host_entry(host: &str, port: u16) -> String {
+    if port == 22 {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "RSA",
+        HostKeyType::Dss => "DSA",
+        HostKeyType::Ecdsa256 | HostKeyType::Ecdsa384 | HostKeyType::Ecdsa521 => "ECDSA",
+        HostKeyType::Ed25519 => "ED25519",
+        HostKeyType::Unknown => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A session whose handshake with `server` failed.
+    fn failed_handshake(server: impl FnOnce(TcpStream) + Send + 'static) -> Session {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || server(listener.accept().unwrap().0));
+        let mut session = Session::new().unwrap();
+        session.set_timeout(500);
+        session.set_tcp_stream(TcpStream::connect(address).unwrap());
+        assert!(session.handshake().is_err());
+        server.join().unwrap();
+        session
+    }
+
+    #[test]
+    fn reopens_a_shell_that_died() {
+        let connection =
+            SshConnection::new("admin", "db1", 22, None, None, 1, ConnectionType::Agent);
+        let mut managed = ManagedSession::new(connection, Ok(Session::new().unwrap()));
+        managed.open_transport = |_, _| Ok(Box::new(RemoteShell::local()?));
+        let shell_pid = |managed: &mut ManagedSession| {
+            let transport = managed.transport.as_mut().unwrap();
+            transport.run("echo $$").unwrap().trim().to_string()
+        };
+
+        let mut stats = Stats::default();
+        assert!(matches!(managed.sample(&mut stats), Sample::Collected));
+        let first = shell_pid(&mut managed);
+        std::process::Command::new("kill")
+            .args(["-9", &first])
+            .status()
+            .unwrap();
+
+        // The sample that finds the shell gone fails, the session stays.
+        let Sample::Failed(error) = managed.sample(&mut stats) else {
+            panic!("the sample should fail without reconnecting");
+        };
+        assert!(error.starts_with("Channel error: "), "{}", error);
+        assert!(managed.transport.is_none());
+        assert!(managed.session.is_some());
+
+        assert!(matches!(managed.sample(&mut stats), Sample::Collected));
+        assert_ne!(shell_pid(&mut managed), first);
+        assert!(!stats.hostname.is_empty());
+    }
+
+    #[test]
+    fn tells_a_lost_connection_from_a_failed_command() {
+        let session = Session::new().unwrap();
+        assert!(!session_lost(&session, false));
+        assert!(!session_lost(&session, true));
+
+        let closed = failed_handshake(drop);
+        assert!(session_lost(&closed, false));
+
+        // A server that stops answering times out, which only counts as lost
+        // while opening a channel: otherwise a command may just be hanging.
+        let silent = failed_handshake(|mut stream| {
+            stream.write_all(b"SSH-2.0-OpenSSH_9.2\r\n").unwrap();
+            thread::sleep(Duration::from_millis(1500));
+        });
+        assert!(!session_lost(&silent, false));
+        assert!(session_lost(&silent, true));
+    }
+
+    #[test]
+    fn append_known_host_keeps_the_file() {
+        let dir = env::temp_dir().join(format!("rsshtop-known-hosts-{}", std::process::id()));
+        let path = dir.join("known_hosts");
+        fs::create_dir_all(&dir).unwrap();
+        let existing = "# work hosts\n@cert-authority *.corp ssh-ed25519 AAAA\nold ssh-rsa AAAB";
+        fs::write(&path, existing).unwrap();
+
+        let mut key = 11u32.to_be_bytes().to_vec();
+        key.extend_from_slice(b"ssh-ed25519");
+        key.extend_from_slice(&[0, 0, 0, 1, 42]);
+        append_known_host(&path, "[db1]:2222", &key).unwrap();
+        append_known_host(&path, "web1", &key).unwrap();
+
+        let encoded = STANDARD.encode(&key);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!(
+                "{}\n[db1]:2222 ssh-ed25519 {}\nweb1 ssh-ed25519 {}\n",
+                existing, encoded, encoded
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
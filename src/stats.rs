@@ -1,27 +1,35 @@
+use crate::disks::{self, DiskCounters, DiskInfo};
+use crate::error::{MonitorError, SectionError};
+use crate::filesystems::{self, FileSystemInfo};
+use crate::memory::MemInfo;
+use crate::processes::{self, ProcessInfo, ProcessTicks};
 use ::time::Duration;
 use colored::Colorize;
-use ssh2::Session;
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
-use std::io::Read;
+use std::time::Instant;
 
-const ESC: &str = "\x1B[2J\x1B[1;1H"; // Clears the terminal.
-
-pub struct FileSystemInfo {
-    mount_point: String,
-    used: u64,
-    free: u64,
-}
+pub const ESC: &str = "\x1B[2J\x1B[1;1H"; // Clears the terminal.
+const TOP_PROCESSES: usize = 10;
 
+#[derive(Default, Clone)]
 pub struct NetIntfInfo {
-    ipv4: String,
-    ipv6: String,
-    rx: u64,
-    tx: u64,
+    pub ipv4: String,
+    pub ipv6: String,
+    pub rx: u64,
+    pub tx: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    /// Per-second rates since the previous sample, 0 until there is one.
+    pub rx_rate: f64,
+    pub tx_rate: f64,
+    pub rx_packet_rate: f64,
+    pub tx_packet_rate: f64,
+    /// When the counters were read, `None` before the first sample.
+    pub sampled_at: Option<Instant>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CpuRaw {
     user: u64,
     nice: u64,
@@ -35,20 +43,20 @@ pub struct CpuRaw {
     total: u64,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct CpuInfo {
-    user: f32,
-    nice: f32,
-    system: f32,
-    idle: f32,
-    iowait: f32,
-    irq: f32,
-    soft_irq: f32,
-    steal: f32,
-    guest: f32,
+    pub user: f32,
+    pub nice: f32,
+    pub system: f32,
+    pub idle: f32,
+    pub iowait: f32,
+    pub irq: f32,
+    pub soft_irq: f32,
+    pub steal: f32,
+    pub guest: f32,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Stats {
     pub uptime: f64,
     pub hostname: String,
@@ -57,16 +65,23 @@ pub struct Stats {
     pub load10: String,
     pub running_procs: String,
     pub total_procs: String,
-    pub mem_total: u64,
-    pub mem_free: u64,
-    pub mem_buffers: u64,
-    pub mem_cached: u64,
-    pub swap_total: u64,
-    pub swap_free: u64,
+    pub memory: MemInfo,
     pub fs_infos: Vec<FileSystemInfo>,
+    /// Sorted by device name.
+    pub disks: Vec<DiskInfo>,
+    pub prev_disk_counters: DiskCounters,
     pub net_intf: HashMap<String, NetIntfInfo>,
     pub prev_cpu: CpuRaw,
     pub cpu: CpuInfo,
+    /// Raw counters of every `cpuN` line, by core number.
+    pub prev_cores: BTreeMap<usize, CpuRaw>,
+    pub cores: BTreeMap<usize, CpuInfo>,
+    /// Sorted by CPU usage, busiest first.
+    pub processes: Vec<ProcessInfo>,
+    pub prev_process_ticks: ProcessTicks,
+    /// Collectors that failed in the last sample. Their figures are left from
+    /// the sample before.
+    pub failed: Vec<SectionError>,
 }
 impl Display for Stats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -74,13 +89,83 @@ impl Display for Stats {
         for fs in &self.fs_infos {
             file_sys.push_str(
                 format!(
-                    "{}: {} free of {}\n",
+                    "\t{} ({} on {}): {} used, {} free of {} ({:.0}%)",
                     fs.mount_point,
+                    fs.fs_type,
+                    fs.device,
+                    format_bytes(fs.used),
                     format_bytes(fs.free),
-                    format_bytes(fs.used + fs.free)
+                    format_bytes(fs.size),
+                    fs.used_percent
                 )
                 .as_str(),
             );
+            match fs.inodes_percent() {
+                Some(percent) => file_sys.push_str(
+                    format!(
+                        ", inodes {} of {} ({:.0}%)\n",
+                        fs.inodes_used, fs.inodes, percent
+                    )
+                    .as_str(),
+                ),
+                None => file_sys.push('\n'),
+            }
+        }
+
+        let mut cores = String::new();
+        for (i, (id, core)) in self.cores.iter().enumerate() {
+            let cell = format!(
+                "{:>4} [{}] {:>5.1}%",
+                format!("cpu{}", id),
+                usage_bar(core.busy(), 10),
+                core.busy()
+            );
+            let cell = if core.busy() >= 90.0 {
+                cell.bright_red().to_string()
+            } else if core.busy() >= 70.0 {
+                cell.bright_yellow().to_string()
+            } else {
+                cell
+            };
+            cores.push_str(if i % 4 == 0 { "\t" } else { "  " });
+            cores.push_str(&cell);
+            if i % 4 == 3 || i + 1 == self.cores.len() {
+                cores.push('\n');
+            }
+        }
+
+        let mut procs = format!(
+            "\t{:>7} {:<10} {} {:>6} {:>11}  {}\n",
+            "PID", "USER", "S", "CPU%", "RSS", "COMMAND"
+        );
+        for process in self.processes.iter().take(TOP_PROCESSES) {
+            let command = process.command.chars().take(60).collect::<String>();
+            procs.push_str(&format!(
+                "\t{:>7} {:<10} {} {:>6.1} {:>11}  {}\n",
+                process.pid,
+                process.user.chars().take(10).collect::<String>(),
+                process.state,
+                process.cpu,
+                format_bytes(process.rss),
+                command
+            ));
+        }
+
+        let mut disk_io = format!(
+            "\t{:<12} {:>12} {:>12} {:>8} {:>8} {:>9} {:>6}\n",
+            "DEVICE", "READ/S", "WRITE/S", "R/S", "W/S", "AWAIT", "UTIL%"
+        );
+        for disk in &self.disks {
+            disk_io.push_str(&format!(
+                "\t{:<12} {:>12} {:>12} {:>8.1} {:>8.1} {:>7.1}ms {:>6.1}\n",
+                disk.name,
+                format_bytes(disk.read_rate as u64),
+                format_bytes(disk.write_rate as u64),
+                disk.read_iops,
+                disk.write_iops,
+                disk.await_ms,
+                disk.util
+            ));
         }
 
         let mut net_info = String::new();
@@ -94,7 +179,17 @@ impl Display for Stats {
             }
             net_info.push_str(
                 format!(
-                    "\trx = {}, tx = {}\n\n",
+                    "\trx = {}/s ({:.0} pkt/s), tx = {}/s ({:.0} pkt/s)\n",
+                    format_bytes(val.rx_rate as u64),
+                    val.rx_packet_rate,
+                    format_bytes(val.tx_rate as u64),
+                    val.tx_packet_rate
+                )
+                .as_str(),
+            );
+            net_info.push_str(
+                format!(
+                    "\ttotal rx = {}, tx = {}\n\n",
                     format_bytes(val.rx),
                     format_bytes(val.tx)
                 )
@@ -102,17 +197,62 @@ impl Display for Stats {
             );
         }
 
+        let memory = &self.memory;
+        let mut mem_info = format!(
+            "\ttotal = {}, used = {}, free = {}, available = {}\n",
+            format_bytes(memory.total()),
+            format_bytes(memory.used()),
+            format_bytes(memory.free()),
+            format_bytes(memory.available())
+        );
+        mem_info.push_str(&format!(
+            "\tbuffers = {}, cached = {}, shared = {}, slab = {} ({} reclaimable)\n",
+            format_bytes(memory.buffers()),
+            format_bytes(memory.cached()),
+            format_bytes(memory.shared()),
+            format_bytes(memory.slab()),
+            format_bytes(memory.slab_reclaimable())
+        ));
+        mem_info.push_str(&format!(
+            "\tdirty = {}, writeback = {}, committed = {} of {} limit\n",
+            format_bytes(memory.dirty()),
+            format_bytes(memory.writeback()),
+            format_bytes(memory.committed()),
+            format_bytes(memory.commit_limit())
+        ));
+        if memory.huge_pages_total() > 0 {
+            mem_info.push_str(&format!(
+                "\thugepages = {} free of {} ({} pages)\n",
+                format_bytes(memory.huge_pages_free()),
+                format_bytes(memory.huge_pages_total()),
+                format_bytes(memory.huge_page_size())
+            ));
+        }
+        mem_info.push_str(&format!(
+            "\tswap = {} free of {}\n",
+            format_bytes(memory.swap_free()),
+            format_bytes(memory.swap_total())
+        ));
+
+        let mut errors = String::new();
+        if !self.failed.is_empty() {
+            errors.push_str(&format!("\n{}\n", "Errors:".bright_red()));
+            for failed in &self.failed {
+                errors.push_str(&format!("\t{}\n", failed));
+            }
+        }
+
         write!(
             f,
-            "{}{}up {}\n\n{}\n\t{} {} {}\n\n{}\n\t{} user, {} sys, {} nice, {} idle, {} iowait, {} hardirq, {} softirq, {} guest\n\n{}\n\t{} running of {} total\n\n{}\n\tfree = {}\n\tused = {}\n\tbuffers = {}\n\tcached = {}\n\tswap = {} free of {}\n\n{}\n\t{}\n{}\n{}\n",
+            "{}{}up {}\n\n{}\n\t{} {} {}\n\n{}\n\t{} user, {} sys, {} nice, {} idle, {} iowait, {} hardirq, {} softirq, {} guest\n{}\n{}\n\t{} running of {} total\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
             ESC,
             self.hostname.bold().bright_green(),
             self.format_uptime().bold().bright_cyan(),
-            "Load:".bright_yellow(),
+            self.heading("Load:", &["loadavg"]),
             self.load1.bold().bright_white(),
             self.load5.bold().bright_white(),
             self.load10.bold().bright_white(),
-            "CPU:".bright_yellow(),
+            self.heading("CPU:", &["stat"]),
             self.cpu.user.to_string().bold().bright_white(),
             self.cpu.system.to_string().bold().bright_white(),
             self.cpu.nice.to_string().bold().bright_white(),
@@ -121,47 +261,91 @@ impl Display for Stats {
             self.cpu.irq.to_string().bold().bright_white(),
             self.cpu.soft_irq.to_string().bold().bright_white(),
             self.cpu.guest.to_string().bold().bright_white(),
-            "Processes:".bright_yellow(),
+            cores,
+            self.heading("Processes:", &["processes"]),
             self.running_procs.bold().bright_white(),
             self.total_procs.bold().bright_white(),
-            "Memory:".bright_yellow(),
-            format_bytes(self.mem_free).bold().bright_white(),
-            format_bytes(self.mem_total - self.mem_free - self.mem_buffers - self.mem_cached).bold().bright_white(),
-            format_bytes(self.mem_buffers).bold().bright_white(),
-            format_bytes(self.mem_cached).bold().bright_white(),
-            format_bytes(self.swap_free).bold().bright_white(),
-            format_bytes(self.swap_total).bold().bright_white(),
-            "Filesystems:".bright_yellow(),
+            procs,
+            self.heading("Memory:", &["meminfo"]),
+            mem_info.bold().bright_white(),
+            self.heading("Filesystems:", &["filesystems"]),
             file_sys.bold().bright_white(),
-            "Network Interfaces:".bright_yellow(),
+            self.heading("Disk I/O:", &["disks"]),
+            disk_io.bold().bright_white(),
+            self.heading("Network Interfaces:", &["interfaces", "net/dev"]),
             net_info.bold().bright_white(),
+            errors,
         )
     }
 }
 
 impl Stats {
-    pub fn get_all_stats(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        self.get_uptime(session)?;
-        self.get_hostname(session)?;
-        self.get_load(session)?;
-        self.get_mem_info(session)?;
-        self.get_fs_info(session)?;
-        self.get_interfaces(session)?;
-        self.get_interface_info(session)?;
-        self.get_cpu(session)?;
-        Ok(())
+    /// Busy share of the CPU in percent, 0 until two samples have been taken.
+    pub fn cpu_usage(&self) -> f32 {
+        self.cpu.busy()
+    }
+
+    /// Used share of the memory in percent, available memory counting as free.
+    pub fn mem_usage(&self) -> f32 {
+        self.memory.usage()
     }
 
-    fn get_uptime(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let parts = run_command(session, "/bin/cat /proc/uptime")?;
+    /// The filesystem with the highest usage, as mount point and percent used.
+    pub fn fullest_fs(&self) -> Option<(&str, f32)> {
+        self.fs_infos
+            .iter()
+            .map(|fs| (fs.mount_point.as_str(), fs.used_percent))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Received plus transmitted bytes per second over all interfaces.
+    pub fn net_rate(&self) -> f64 {
+        self.net_intf
+            .values()
+            .map(|intf| intf.rx_rate + intf.tx_rate)
+            .sum()
+    }
+
+    /// The first failure in the last sample among the collectors named in `sections`.
+    pub fn failure(&self, sections: &[&str]) -> Option<&SectionError> {
+        self.failed
+            .iter()
+            .find(|failed| sections.contains(&failed.section.as_str()))
+    }
+
+    /// A section heading, in red and marked when the section's collectors failed.
+    fn heading(&self, title: &str, sections: &[&str]) -> String {
+        match self.failure(sections) {
+            Some(_) => format!("{} (failed)", title)
+                .bright_red()
+                .bold()
+                .to_string(),
+            None => title.bright_yellow().to_string(),
+        }
+    }
+
+    /// Forgets the previous CPU counters, so the next sample after a reconnect
+    /// is not compared against counters from before the outage.
+    pub fn reset_baselines(&mut self) {
+        self.prev_cpu = CpuRaw::default();
+        self.prev_cores.clear();
+        self.prev_process_ticks = ProcessTicks::default();
+        self.prev_disk_counters = DiskCounters::default();
+        self.net_intf.clear();
+    }
+
+    /// Sets the uptime from /proc/uptime.
+    pub fn parse_uptime(&mut self, parts: &str) -> Result<(), MonitorError> {
         // todo! split_whitespace
         let uptime_vec = parts.split_whitespace().collect::<Vec<_>>();
         if uptime_vec.len() == 2 {
-            self.uptime = uptime_vec[0].parse::<f64>()?;
+            self.uptime = uptime_vec[0]
+                .parse::<f64>()
+                .map_err(|e| MonitorError::parse("/proc/uptime", 1, e))?;
         }
         Ok(())
     }
-    fn format_uptime(&self) -> String {
+    pub fn format_uptime(&self) -> String {
         let mut duration = self.uptime;
         duration = duration - (duration % Duration::SECOND.as_seconds_f64());
 
@@ -206,91 +390,55 @@ impl Stats {
         res
     }
 
-    fn get_hostname(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        self.hostname = run_command(session, "/bin/hostname -f")?;
+    /// Sets the host name from `hostname` output.
+    pub fn parse_hostname(&mut self, output: &str) -> Result<(), MonitorError> {
+        self.hostname = output.to_string();
         Ok(())
     }
 
-    fn get_load(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let parts = run_command(session, "/bin/cat /proc/loadavg")?;
+    /// Sets the load averages and task counts from /proc/loadavg.
+    pub fn parse_load(&mut self, parts: &str) -> Result<(), MonitorError> {
         // todo! split_whitespace
         let parts_vec = parts.split(' ').collect::<Vec<_>>();
 
-        if parts_vec.len() == 5 {
-            self.load1 = parts_vec[0].to_string();
-            self.load5 = parts_vec[1].to_string();
-            self.load10 = parts_vec[2].to_string();
-            self.running_procs = parts_vec[3].split('/').collect::<Vec<_>>()[0].to_string();
-            self.total_procs = parts_vec[3].split('/').collect::<Vec<_>>()[1].to_string();
-        }
+        let tasks = parts_vec.get(3).and_then(|tasks| tasks.split_once('/'));
+        let (5, Some((running, total))) = (parts_vec.len(), tasks) else {
+            return Err(MonitorError::parse(
+                "/proc/loadavg",
+                1,
+                "expected 3 loads, running/total tasks and the last PID",
+            ));
+        };
+        self.load1 = parts_vec[0].to_string();
+        self.load5 = parts_vec[1].to_string();
+        self.load10 = parts_vec[2].to_string();
+        self.running_procs = running.to_string();
+        self.total_procs = total.to_string();
         Ok(())
     }
 
-    fn get_mem_info(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let parts = run_command(session, "/bin/cat /proc/meminfo")?;
-        let lines = parts.lines().collect::<Vec<_>>();
-        for line in lines.iter() {
-            let parts = line.split_whitespace().collect::<Vec<_>>();
-            if parts.len() == 3 {
-                match parts[1].parse::<u64>() {
-                    Ok(mut value) => {
-                        value *= 1024;
-                        match parts[0] {
-                            "MemTotal:" => self.mem_total = value,
-                            "MemFree:" => self.mem_free = value,
-                            "Buffers:" => self.mem_buffers = value,
-                            "Cached:" => self.mem_cached = value,
-                            "SwapTotal:" => self.swap_total = value,
-                            "SwapFree:" => self.swap_free = value,
-                            &_ => continue,
-                        }
-                    }
-                    Err(_) => continue,
-                }
-            }
-        }
+    /// Sets the memory and swap figures from /proc/meminfo.
+    pub fn parse_mem_info(&mut self, output: &str) -> Result<(), MonitorError> {
+        self.memory = MemInfo::parse(output)?;
         Ok(())
     }
 
-    fn get_fs_info(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let parts = run_command(session, "/bin/df -B1")?;
-        let lines = parts.lines().collect::<Vec<_>>();
-
-        let mut flag = 0;
-        for line in lines {
-            let parts = line.split_whitespace().collect::<Vec<_>>();
-            let dev = !parts.is_empty() && parts[0].starts_with("/dev/");
-            if parts.len() == 1 && dev {
-                flag = 1;
-            } else if (parts.len() == 5 && flag == 1) || (parts.len() == 6 && dev) {
-                let temp = flag;
-                flag = 0;
-                let used = parts[2 - temp].parse::<u64>();
-                if used.is_err() {
-                    continue;
-                }
-                let free = parts[3 - temp].parse::<u64>();
-                if free.is_err() {
-                    continue;
-                }
-
-                self.fs_infos = Vec::new();
-                self.fs_infos.push(FileSystemInfo {
-                    mount_point: parts[5 - temp].to_string(),
-                    used: used.unwrap(),
-                    free: free.unwrap(),
-                });
-            }
-        }
-
+    /// Sets the filesystems from `filesystems::FS_COMMAND` output.
+    pub fn parse_fs_info(&mut self, output: &str) -> Result<(), MonitorError> {
+        self.fs_infos = filesystems::parse(output);
         Ok(())
     }
 
-    #[allow(clippy::collapsible_else_if)]
-    fn get_interfaces(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let interfaces = run_command(session, "/bin/ip -o addr")
-            .or_else(|_| run_command(session, "/sbin/ip -o addr"))?;
+    /// Sets the disk I/O rates from `disks::DISK_COMMAND` output.
+    pub fn parse_disks(&mut self, output: &str) -> Result<(), MonitorError> {
+        let (disks, counters) = disks::parse(output, &self.prev_disk_counters);
+        self.disks = disks;
+        self.prev_disk_counters = counters;
+        Ok(())
+    }
 
+    /// Adds the addresses in `ip -o addr` output to the interfaces.
+    pub fn parse_interfaces(&mut self, interfaces: &str) -> Result<(), MonitorError> {
         let lines = interfaces.lines().collect::<Vec<_>>();
         for line in lines {
             let fields = line.split_whitespace().collect::<Vec<_>>();
@@ -298,98 +446,176 @@ impl Stats {
                 let ipv4 = fields[2] == "inet";
                 let int_name = fields[1];
 
-                if let Some(value) = self.net_intf.get_mut(int_name) {
-                    if ipv4 {
-                        value.ipv4 = fields[3].to_string();
-                    } else {
-                        value.ipv6 = fields[3].to_string();
-                    }
+                let value = self.net_intf.entry(int_name.to_string()).or_default();
+                if ipv4 {
+                    value.ipv4 = fields[3].to_string();
                 } else {
-                    if ipv4 {
-                        self.net_intf.insert(
-                            int_name.to_string(),
-                            NetIntfInfo {
-                                ipv4: fields[3].to_string(),
-                                ipv6: String::new(),
-                                rx: u64::default(),
-                                tx: u64::default(),
-                            },
-                        );
-                    } else {
-                        self.net_intf.insert(
-                            int_name.to_string(),
-                            NetIntfInfo {
-                                ipv4: String::new(),
-                                ipv6: fields[3].to_string(),
-                                rx: u64::default(),
-                                tx: u64::default(),
-                            },
-                        );
-                    }
+                    value.ipv6 = fields[3].to_string();
                 }
             }
         }
         Ok(())
     }
 
-    fn get_interface_info(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        if self.net_intf.is_empty() {
-            return Ok(());
-        }
-        let infos = run_command(session, "/bin/cat /proc/net/dev")?;
-        let lines = infos.lines().collect::<Vec<_>>();
-        for line in lines {
-            let parts = line.split_whitespace().collect::<Vec<_>>();
-            if parts.len() == 17 {
-                let intf = parts[0].trim().trim_matches(':');
-                if let Some(value) = self.net_intf.get_mut(intf) {
-                    let rx = parts[1].parse::<u64>()?;
-                    let tx = parts[9].parse::<u64>()?;
+    /// Updates the counters and rates of the known interfaces from /proc/net/dev.
+    pub fn parse_interface_info(&mut self, infos: &str) -> Result<(), MonitorError> {
+        for (index, line) in infos.lines().enumerate() {
+            // Older kernels leave no space after the colon once the first counter is wide.
+            let Some((intf, counters)) = line.split_once(':') else {
+                continue;
+            };
+            let parts = counters.split_whitespace().collect::<Vec<_>>();
+            if parts.len() == 16 {
+                if let Some(value) = self.net_intf.get_mut(intf.trim()) {
+                    let counter = |field: &str| {
+                        field
+                            .parse::<u64>()
+                            .map_err(|e| MonitorError::parse("/proc/net/dev", index + 1, e))
+                    };
+                    let rx = counter(parts[0])?;
+                    let rx_packets = counter(parts[1])?;
+                    let tx = counter(parts[8])?;
+                    let tx_packets = counter(parts[9])?;
+                    let now = Instant::now();
+
+                    if let Some(sampled_at) = value.sampled_at {
+                        let elapsed = now.duration_since(sampled_at).as_secs_f64();
+                        let rates = (
+                            counter_rate(value.rx, rx, elapsed),
+                            counter_rate(value.tx, tx, elapsed),
+                            counter_rate(value.rx_packets, rx_packets, elapsed),
+                            counter_rate(value.tx_packets, tx_packets, elapsed),
+                        );
+                        // A counter that went backwards means the interface was reset,
+                        // the next sample gets a rate against the new baseline.
+                        if let (Some(rx_rate), Some(tx_rate), Some(rx_pps), Some(tx_pps)) = rates {
+                            value.rx_rate = rx_rate;
+                            value.tx_rate = tx_rate;
+                            value.rx_packet_rate = rx_pps;
+                            value.tx_packet_rate = tx_pps;
+                        } else {
+                            value.rx_rate = 0.0;
+                            value.tx_rate = 0.0;
+                            value.rx_packet_rate = 0.0;
+                            value.tx_packet_rate = 0.0;
+                        }
+                    }
                     value.rx = rx;
                     value.tx = tx;
+                    value.rx_packets = rx_packets;
+                    value.tx_packets = tx_packets;
+                    value.sampled_at = Some(now);
                 }
             }
         }
         Ok(())
     }
 
-    fn get_cpu(&mut self, session: &Session) -> Result<(), Box<dyn Error>> {
-        let cpu = run_command(session, "/bin/cat /proc/stat")?;
+    /// Sets the process table from `processes::PROCESS_COMMAND` output.
+    pub fn parse_processes(&mut self, output: &str) -> Result<(), MonitorError> {
+        let (processes, ticks) = processes::parse(output, &self.prev_process_ticks);
+        self.processes = processes;
+        self.prev_process_ticks = ticks;
+        Ok(())
+    }
+
+    /// Sets the CPU and per-core usage from /proc/stat against the previous sample.
+    pub fn parse_proc_stat(&mut self, cpu: &str) -> Result<(), MonitorError> {
         let lines = cpu.lines().collect::<Vec<_>>();
 
         let mut current_cpu = CpuRaw::default();
+        let mut current_cores = BTreeMap::new();
 
         for line in lines {
             let fields = line.split_whitespace().collect::<Vec<_>>();
-            if !fields.is_empty() && fields[0] == "cpu" {
+            if fields.is_empty() || !fields[0].starts_with("cpu") {
+                continue;
+            }
+            if fields[0] == "cpu" {
                 parse_cpu(&fields, &mut current_cpu);
-                break;
+            } else if let Ok(id) = fields[0][3..].parse::<usize>() {
+                let mut core = CpuRaw::default();
+                parse_cpu(&fields, &mut core);
+                current_cores.insert(id, core);
             }
         }
 
-        if self.prev_cpu.total == 0 {
-            self.prev_cpu = current_cpu;
-            return Ok(());
+        if let Some(cpu) = CpuInfo::between(&self.prev_cpu, &current_cpu) {
+            self.cpu = cpu;
         }
-
-        let total = (current_cpu.total - self.prev_cpu.total) as f32;
-
-        self.cpu.user = (current_cpu.user - self.prev_cpu.user) as f32 / total * 100.0;
-        self.cpu.nice = (current_cpu.nice - self.prev_cpu.nice) as f32 / total * 100.0;
-        self.cpu.system = (current_cpu.system - self.prev_cpu.system) as f32 / total * 100.0;
-        self.cpu.idle = (current_cpu.idle - self.prev_cpu.idle) as f32 / total * 100.0;
-        self.cpu.iowait = (current_cpu.iowait - self.prev_cpu.iowait) as f32 / total * 100.0;
-        self.cpu.irq = (current_cpu.irq - self.prev_cpu.irq) as f32 / total * 100.0;
-        self.cpu.soft_irq = (current_cpu.soft_irq - self.prev_cpu.soft_irq) as f32 / total * 100.0;
-        self.cpu.steal = (current_cpu.steal - self.prev_cpu.steal) as f32 / total * 100.0;
-        self.cpu.guest = (current_cpu.guest - self.prev_cpu.guest) as f32 / total * 100.0;
+        // Cores that just came online have no baseline yet and are left out until the next sample.
+        self.cores = current_cores
+            .iter()
+            .filter_map(|(id, core)| {
+                let prev = self.prev_cores.get(id)?;
+                Some((*id, CpuInfo::between(prev, core)?))
+            })
+            .collect();
 
         self.prev_cpu = current_cpu;
+        self.prev_cores = current_cores;
         Ok(())
     }
 }
 
-fn format_bytes(val: u64) -> String {
+impl CpuInfo {
+    /// Percentages for the interval between two samples. `None` for the first
+    /// sample (also after a reconnect or a reboot), which only sets the baseline.
+    fn between(prev: &CpuRaw, current: &CpuRaw) -> Option<CpuInfo> {
+        if prev.total == 0 || current.total <= prev.total {
+            return None;
+        }
+        let total = (current.total - prev.total) as f32;
+        let percent = |current: u64, prev: u64| current.saturating_sub(prev) as f32 / total * 100.0;
+
+        Some(CpuInfo {
+            user: percent(current.user, prev.user),
+            nice: percent(current.nice, prev.nice),
+            system: percent(current.system, prev.system),
+            idle: percent(current.idle, prev.idle),
+            iowait: percent(current.iowait, prev.iowait),
+            irq: percent(current.irq, prev.irq),
+            soft_irq: percent(current.soft_irq, prev.soft_irq),
+            steal: percent(current.steal, prev.steal),
+            guest: percent(current.guest, prev.guest),
+        })
+    }
+
+    /// Busy share in percent, everything except idle. Guest time is already part of user.
+    pub fn busy(&self) -> f32 {
+        self.user + self.nice + self.system + self.iowait + self.irq + self.soft_irq + self.steal
+    }
+}
+
+/// How close to the end of its range a 32-bit counter must have been, and how
+/// close to 0 it must be now, for a decrease to count as a wrap.
+const WRAP_WINDOW: u64 = 1 << 30;
+
+/// Per-second rate between two readings of a counter. Wraps of 32-bit counters
+/// (still used by some drivers) are accounted for; any other decrease is a reset
+/// (an interface going down and up, a driver reload) and yields `None`.
+fn counter_rate(prev: u64, current: u64, elapsed: f64) -> Option<f64> {
+    if elapsed <= 0.0 {
+        return None;
+    }
+    let max = u32::MAX as u64;
+    let delta = if current >= prev {
+        current - prev
+    } else if prev <= max && prev > max - WRAP_WINDOW && current < WRAP_WINDOW {
+        current + (max + 1) - prev
+    } else {
+        return None;
+    };
+    Some(delta as f64 / elapsed)
+}
+
+/// A text gauge like `████░░░░░░` for a percentage.
+pub fn usage_bar(percent: f32, width: usize) -> String {
+    let filled = ((percent / 100.0 * width as f32).round() as usize).min(width);
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+pub fn format_bytes(val: u64) -> String {
     if val < 1024 {
         format!("{} bytes", val)
     } else if val < 1024 * 1024 {
@@ -421,10 +647,120 @@ fn parse_cpu(fields: &Vec<&str>, cpu: &mut CpuRaw) {
     }
 }
 
-fn run_command(session: &Session, command: &str) -> Result<String, Box<dyn Error>> {
-    let mut channel = session.channel_session()?;
-    let mut result = String::new();
-    channel.exec(command)?;
-    channel.read_to_string(&mut result)?;
-    Ok(result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_load() {
+        let mut stats = Stats::default();
+        stats.parse_load("0.52 0.58 0.59 3/1204 98765\n").unwrap();
+        assert_eq!(
+            (
+                stats.load1.as_str(),
+                stats.load5.as_str(),
+                stats.load10.as_str()
+            ),
+            ("0.52", "0.58", "0.59")
+        );
+        assert_eq!(stats.running_procs, "3");
+        assert_eq!(stats.total_procs.trim(), "1204");
+    }
+
+    #[test]
+    fn rejects_malformed_load() {
+        let mut stats = Stats::default();
+        assert!(stats.parse_load("0.52 0.58 0.59 3/1204").is_err());
+        assert!(stats.parse_load("0.52 0.58 0.59 1204 98765").is_err());
+        assert!(stats.parse_load("").is_err());
+        assert_eq!(stats.load1, "");
+    }
+
+    #[test]
+    fn parses_cpu_fields() {
+        let fields = "cpu  4705 356 584 3699 23 23 0 12 0 0"
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let mut cpu = CpuRaw::default();
+        parse_cpu(&fields, &mut cpu);
+        assert_eq!(
+            (cpu.user, cpu.nice, cpu.system, cpu.idle, cpu.iowait),
+            (4705, 356, 584, 3699, 23)
+        );
+        assert_eq!(
+            (cpu.irq, cpu.soft_irq, cpu.steal, cpu.guest),
+            (23, 0, 12, 0)
+        );
+        assert_eq!(cpu.total, 9402);
+    }
+
+    #[test]
+    fn cpu_usage_is_the_share_of_ticks_between_samples() {
+        let mut stats = Stats::default();
+        stats
+            .parse_proc_stat("cpu  100 0 100 800 0 0 0 0\ncpu0 50 0 50 400 0 0 0 0\ncpu1 50 0 50 400 0 0 0 0\nintr 1\n")
+            .unwrap();
+        // The first sample only sets the baseline.
+        assert_eq!(stats.cpu.busy(), 0.0);
+        assert!(stats.cores.is_empty());
+
+        stats
+            .parse_proc_stat("cpu  250 0 150 900 100 0 0 0\ncpu0 200 0 100 400 0 0 0 0\ncpu1 50 0 50 500 100 0 0 0\n")
+            .unwrap();
+        assert_eq!(stats.cpu.user, 37.5);
+        assert_eq!(stats.cpu.system, 12.5);
+        assert_eq!(stats.cpu.idle, 25.0);
+        assert_eq!(stats.cpu.iowait, 25.0);
+        assert_eq!(stats.cores[&0].busy(), 100.0);
+        assert_eq!(stats.cores[&1].busy(), 50.0);
+    }
+
+    #[test]
+    fn parses_net_dev_without_a_space_after_the_colon() {
+        let mut stats = Stats::default();
+        stats
+            .net_intf
+            .insert("eth0".to_string(), NetIntfInfo::default());
+        stats
+            .net_intf
+            .insert("lo".to_string(), NetIntfInfo::default());
+        // Older kernels print the first counter right after the colon once it is wide.
+        stats
+            .parse_interface_info(concat!(
+                "Inter-|   Receive                                                |  Transmit\n",
+                " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n",
+                "    lo:  123456     789    0    0    0     0          0         0   123456     789    0    0    0     0       0          0\n",
+                "  eth0:4123456789 5123456    0    0    0     0          0       12 987654321 4123456    0    0    0     0       0          0\n",
+            ))
+            .unwrap();
+        let eth0 = &stats.net_intf["eth0"];
+        assert_eq!((eth0.rx, eth0.rx_packets), (4123456789, 5123456));
+        assert_eq!((eth0.tx, eth0.tx_packets), (987654321, 4123456));
+        let lo = &stats.net_intf["lo"];
+        assert_eq!((lo.rx, lo.tx), (123456, 123456));
+    }
+
+    #[test]
+    fn counter_rate_counts_increases() {
+        assert_eq!(counter_rate(1000, 3000, 2.0), Some(1000.0));
+        assert_eq!(counter_rate(1000, 1000, 2.0), Some(0.0));
+        assert_eq!(counter_rate(1000, 3000, 0.0), None);
+    }
+
+    #[test]
+    fn counter_rate_accounts_for_32_bit_wraps() {
+        let prev = u32::MAX as u64 - 999;
+        assert_eq!(counter_rate(prev, 1000, 1.0), Some(2000.0));
+    }
+
+    #[test]
+    fn counter_rate_treats_other_decreases_as_resets() {
+        // A 32-bit counter far from the end of its range was reset, not wrapped.
+        assert_eq!(counter_rate(3_000_000_000, 1000, 1.0), None);
+        assert_eq!(counter_rate(2_000_000, 1000, 1.0), None);
+        // Near the end, but the new reading is too far along for a wrap.
+        assert_eq!(counter_rate(u32::MAX as u64 - 10, 2_000_000_000, 1.0), None);
+        // 64-bit counters don't wrap in practice.
+        assert_eq!(counter_rate(10_000_000_000, 1000, 1.0), None);
+    }
 }
@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// A `[user@]host[:port]` destination as given on the command line.
+/// `host` is a DNS name, an ssh config alias, or an IP literal (without brackets).
+#[derive(Debug, PartialEq)]
+pub struct Target {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TargetError {
+    Empty,
+    EmptyUser,
+    EmptyHost,
+    InvalidPort(String),
+    UnclosedBracket,
+    UnexpectedAfterBracket(String),
+}
+
+impl Display for TargetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetError::Empty => write!(f, "no host given, expected [user@]host[:port]"),
+            TargetError::EmptyUser => write!(f, "empty user name before '@'"),
+            TargetError::EmptyHost => write!(f, "empty host name"),
+            TargetError::InvalidPort(port) => write!(f, "invalid port '{}'", port),
+            TargetError::UnclosedBracket => write!(f, "missing ']' after IPv6 address"),
+            TargetError::UnexpectedAfterBracket(rest) => {
+                write!(
+                    f,
+                    "unexpected '{}' after IPv6 address, expected :port",
+                    rest
+                )
+            }
+        }
+    }
+}
+
+impl Error for TargetError {}
+
+/// Parses `[user@]host[:port]`. IPv6 literals need brackets when a port is given
+/// (`[fe80::1]:22`); a bare address with several colons is taken as a host without port.
+pub fn parse_target(input: &str) -> Result<Target, TargetError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(TargetError::Empty);
+    }
+
+    let (user, rest) = match input.rsplit_once('@') {
+        Some(("", _)) => return Err(TargetError::EmptyUser),
+        Some((user, rest)) => (Some(user.to_string()), rest),
+        None => (None, input),
+    };
+
+    let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+        let (host, after) = bracketed
+            .split_once(']')
+            .ok_or(TargetError::UnclosedBracket)?;
+        match after {
+            "" => (host, None),
+            _ => match after.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => return Err(TargetError::UnexpectedAfterBracket(after.to_string())),
+            },
+        }
+    } else if rest.matches(':').count() > 1 {
+        (rest, None)
+    } else {
+        match rest.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (rest, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(TargetError::EmptyHost);
+    }
+    let port = match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(port) if port != 0 => Some(port),
+            _ => return Err(TargetError::InvalidPort(port.to_string())),
+        },
+        None => None,
+    };
+
+    Ok(Target {
+        user,
+        host: host.to_string(),
+        port,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(user: Option<&str>, host: &str, port: Option<u16>) -> Target {
+        Target {
+            user: user.map(str::to_string),
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parses_user_host_and_port() {
+        assert_eq!(
+            parse_target("admin@db1.example.com:2222"),
+            Ok(target(Some("admin"), "db1.example.com", Some(2222)))
+        );
+        assert_eq!(parse_target(" db1 "), Ok(target(None, "db1", None)));
+        assert_eq!(
+            parse_target("admin@10.0.0.5"),
+            Ok(target(Some("admin"), "10.0.0.5", None))
+        );
+        assert_eq!(
+            parse_target("10.0.0.5:22"),
+            Ok(target(None, "10.0.0.5", Some(22)))
+        );
+        // The host follows the last '@', a user name may contain one.
+        assert_eq!(
+            parse_target("jane@corp@bastion"),
+            Ok(target(Some("jane@corp"), "bastion", None))
+        );
+    }
+
+    #[test]
+    fn parses_ipv6() {
+        assert_eq!(parse_target("[::1]:22"), Ok(target(None, "::1", Some(22))));
+        assert_eq!(
+            parse_target("root@[fe80::1%eth0]:2222"),
+            Ok(target(Some("root"), "fe80::1%eth0", Some(2222)))
+        );
+        assert_eq!(parse_target("[::1]"), Ok(target(None, "::1", None)));
+        // Without brackets the colons are all part of the address.
+        assert_eq!(
+            parse_target("2001:db8::22"),
+            Ok(target(None, "2001:db8::22", None))
+        );
+        assert_eq!(
+            parse_target("root@::1"),
+            Ok(target(Some("root"), "::1", None))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ports() {
+        for (input, port) in [
+            ("db1:", ""),
+            ("db1:0", "0"),
+            ("db1:65536", "65536"),
+            ("db1:ssh", "ssh"),
+            ("db1:-22", "-22"),
+            ("[::1]:", ""),
+            ("[::1]:x", "x"),
+        ] {
+            assert_eq!(
+                parse_target(input),
+                Err(TargetError::InvalidPort(port.to_string())),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(parse_target("db1:65535").unwrap().port, Some(65535));
+    }
+
+    #[test]
+    fn rejects_malformed_targets() {
+        assert_eq!(parse_target("  "), Err(TargetError::Empty));
+        assert_eq!(parse_target("@db1"), Err(TargetError::EmptyUser));
+        assert_eq!(parse_target("admin@"), Err(TargetError::EmptyHost));
+        assert_eq!(parse_target(":22"), Err(TargetError::EmptyHost));
+        assert_eq!(parse_target("[]:22"), Err(TargetError::EmptyHost));
+        assert_eq!(parse_target("[::1:22"), Err(TargetError::UnclosedBracket));
+        assert_eq!(
+            parse_target("[::1]22"),
+            Err(TargetError::UnexpectedAfterBracket("22".to_string()))
+        );
+    }
+}
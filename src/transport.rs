@@ -0,0 +1,71 @@
+use crate::error::MonitorError;
+use crate::shell;
+use std::fs;
+
+/// How collectors reach a host: by running shell commands, or by reading files
+/// where commands can't be run.
+pub trait Transport: Send {
+    /// Runs a shell script and returns its standard output.
+    fn run(&mut self, script: &str) -> Result<String, MonitorError>;
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError>;
+
+    /// Names of the entries in the directory `path`.
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError>;
+
+    /// Size and usage of the filesystem mounted at `path`, like statvfs(3).
+    fn fs_usage(&mut self, path: &str) -> Result<FsUsage, MonitorError> {
+        Err(MonitorError::Read {
+            path: path.to_string(),
+            message: "statvfs is not supported".to_string(),
+        })
+    }
+
+    /// Whether `run` works. If not, collectors read files instead.
+    fn runs_commands(&self) -> bool {
+        true
+    }
+
+    /// Set once the transport has failed in a way that needs it reopened.
+    fn broken(&self) -> bool {
+        false
+    }
+}
+
+/// Filesystem usage in blocks of `block_size` bytes, as from statvfs(3).
+pub struct FsUsage {
+    pub block_size: u64,
+    pub blocks: u64,
+    pub blocks_free: u64,
+    /// Free blocks available to unprivileged users.
+    pub blocks_available: u64,
+    pub inodes: u64,
+    pub inodes_free: u64,
+}
+
+/// The machine this runs on, using the local `sh` and filesystem.
+pub struct Local;
+
+impl Transport for Local {
+    fn run(&mut self, script: &str) -> Result<String, MonitorError> {
+        shell::run_local(script)
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+        fs::read_to_string(path).map_err(|e| read_error(path, e))
+    }
+
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError> {
+        let entries = fs::read_dir(path).map_err(|e| read_error(path, e))?;
+        Ok(entries
+            .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().into_owned()))
+            .collect())
+    }
+}
+
+pub(crate) fn read_error(path: &str, e: impl std::fmt::Display) -> MonitorError {
+    MonitorError::Read {
+        path: path.to_string(),
+        message: e.to_string(),
+    }
+}
@@ -0,0 +1,973 @@
+use crate::fleet::{Fleet, HostState, HostStatus};
+use crate::replay::{self, Player};
+use crossbeam_channel::{select, tick, unbounded, Receiver};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Sparkline, Table, TableState};
+use remote_monitoring::processes::{self, ProcessSort};
+use remote_monitoring::stats::{format_bytes, usage_bar, Stats};
+use std::collections::VecDeque;
+use std::io::{self, Stdout};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_INTERVAL: usize = 3600;
+const REPLAY_FRAME: Duration = Duration::from_millis(100);
+/// Seconds of recording skipped by the arrow keys, page keys skip 30 times as much.
+const SEEK_STEP: f64 = 10.0;
+// "cpu12 ████████ 100%" plus spacing.
+const CORE_CELL_WIDTH: u16 = 22;
+const MAX_DISK_ROWS: usize = 6;
+const MAX_CORE_ROWS: usize = 8;
+
+struct App {
+    interval: usize,
+    paused: bool,
+    process_sort: ProcessSort,
+    /// Host shown in full, `None` while the fleet overview is shown.
+    selected: Option<usize>,
+    /// Highlighted row of the fleet overview.
+    cursor: usize,
+    hosts: usize,
+}
+
+enum Action {
+    None,
+    Quit,
+    IntervalChanged,
+}
+
+/// Full-screen terminal UI. With a single host it opens straight into the host view,
+/// otherwise it starts with the fleet overview.
+pub fn run(fleet: Fleet, interval: usize) -> io::Result<()> {
+    with_terminal(|terminal| event_loop(terminal, &fleet, interval))
+}
+
+/// The terminal UI over a recording, with play/pause, seek and speed keys.
+pub fn run_replay(mut player: Player) -> io::Result<()> {
+    with_terminal(|terminal| replay_loop(terminal, &mut player))
+}
+
+fn with_terminal(
+    run: impl FnOnce(&mut Terminal<CrosstermBackend<Stdout>>) -> io::Result<()>,
+) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        hook(info);
+    }));
+
+    let result = Terminal::new(CrosstermBackend::new(io::stdout())).and_then(|mut terminal| {
+        terminal.hide_cursor()?;
+        run(&mut terminal)
+    });
+    restore()?;
+    result
+}
+
+fn restore() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, crossterm::cursor::Show)
+}
+
+impl App {
+    fn new(hosts: usize, interval: usize) -> Self {
+        App {
+            interval: interval.max(1),
+            paused: false,
+            process_sort: ProcessSort::Cpu,
+            selected: if hosts == 1 { Some(0) } else { None },
+            cursor: 0,
+            hosts,
+        }
+    }
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    fleet: &Fleet,
+    interval: usize,
+) -> io::Result<()> {
+    let mut app = App::new(fleet.states.len(), interval);
+    let events = terminal_events();
+    let mut ticks = tick(Duration::from_secs(app.interval as u64));
+    fleet.tick();
+
+    loop {
+        terminal.draw(|frame| draw(frame, &fleet.states, &app, None))?;
+        select! {
+            recv(ticks) -> _ => {
+                if !app.paused {
+                    fleet.tick();
+                }
+            }
+            recv(fleet.updates) -> _ => {}
+            recv(events) -> event => match event {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    match handle_key(&mut app, key) {
+                        Action::Quit => return Ok(()),
+                        Action::IntervalChanged => {
+                            ticks = tick(Duration::from_secs(app.interval as u64));
+                        }
+                        Action::None => {}
+                    }
+                }
+                // Resizes only need the redraw at the top of the loop.
+                Ok(_) => {}
+                Err(_) => return Ok(()),
+            },
+        }
+    }
+}
+
+fn replay_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    player: &mut Player,
+) -> io::Result<()> {
+    let mut app = App::new(player.states.len(), 1);
+    let events = terminal_events();
+    let frames = tick(REPLAY_FRAME);
+    let mut last = Instant::now();
+
+    loop {
+        terminal.draw(|frame| draw(frame, &player.states, &app, Some(player)))?;
+        select! {
+            recv(frames) -> _ => {
+                let now = Instant::now();
+                player.advance(now - last);
+                last = now;
+            }
+            recv(events) -> event => match event {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if !handle_replay_key(player, key) {
+                        if let Action::Quit = handle_key(&mut app, key) {
+                            return Ok(());
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Returns false for keys that are not replay controls.
+fn handle_replay_key(player: &mut Player, key: KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('p') | KeyCode::Char(' ') => player.paused = !player.paused,
+        KeyCode::Left | KeyCode::Char('h') => player.seek(-SEEK_STEP),
+        KeyCode::Right | KeyCode::Char('l') => player.seek(SEEK_STEP),
+        KeyCode::PageUp => player.seek(-SEEK_STEP * 30.0),
+        KeyCode::PageDown => player.seek(SEEK_STEP * 30.0),
+        KeyCode::Home => player.seek_to(0.0),
+        KeyCode::End => player.seek_to(player.duration()),
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            player.speed = (player.speed * 2.0).min(replay::MAX_SPEED)
+        }
+        KeyCode::Char('-') => player.speed = (player.speed / 2.0).max(replay::MIN_SPEED),
+        _ => return false,
+    }
+    true
+}
+
+fn terminal_events() -> Receiver<Event> {
+    let (sender, receiver) = unbounded();
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+fn handle_key(app: &mut App, key: KeyEvent) -> Action {
+    match key.code {
+        KeyCode::Char('q') => Action::Quit,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+        KeyCode::Char('s') => {
+            app.process_sort = match app.process_sort {
+                ProcessSort::Cpu => ProcessSort::Memory,
+                ProcessSort::Memory => ProcessSort::Pid,
+                ProcessSort::Pid => ProcessSort::Cpu,
+            };
+            Action::None
+        }
+        KeyCode::Char('p') | KeyCode::Char(' ') => {
+            app.paused = !app.paused;
+            Action::None
+        }
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            app.interval = (app.interval + 1).min(MAX_INTERVAL);
+            Action::IntervalChanged
+        }
+        KeyCode::Char('-') => {
+            app.interval = app.interval.saturating_sub(1).max(1);
+            Action::IntervalChanged
+        }
+        KeyCode::Up | KeyCode::Char('k') if app.selected.is_none() => {
+            app.cursor = app.cursor.saturating_sub(1);
+            Action::None
+        }
+        KeyCode::Down | KeyCode::Char('j') if app.selected.is_none() => {
+            app.cursor = (app.cursor + 1).min(app.hosts.saturating_sub(1));
+            Action::None
+        }
+        KeyCode::Enter if app.selected.is_none() && app.hosts > 0 => {
+            app.selected = Some(app.cursor);
+            Action::None
+        }
+        KeyCode::Esc | KeyCode::Backspace if app.hosts > 1 => {
+            app.selected = None;
+            Action::None
+        }
+        _ => Action::None,
+    }
+}
+
+fn draw(frame: &mut Frame, states: &[Arc<Mutex<HostState>>], app: &App, player: Option<&Player>) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)])
+        .split(frame.size());
+
+    match app.selected {
+        Some(index) => draw_host(
+            frame,
+            chunks[0],
+            &states[index].lock().unwrap(),
+            app.process_sort,
+        ),
+        None => draw_overview(frame, chunks[0], states, app.cursor),
+    }
+
+    let mut keys = vec![key_hint("q", "quit"), key_hint("p", "pause")];
+    match player {
+        Some(player) => {
+            keys.push(key_hint("←→", "seek"));
+            keys.push(key_hint("+/-", &format!("speed x{}", player.speed)));
+        }
+        None => keys.push(key_hint("+/-", &format!("interval {}s", app.interval))),
+    }
+    keys.push(key_hint("s", "sort processes"));
+    if app.hosts > 1 {
+        keys.push(key_hint("↑↓ Enter", "select"));
+        keys.push(key_hint("Esc", "overview"));
+    }
+    let mut status = Vec::new();
+    if let Some(player) = player {
+        status.push(Span::styled(
+            format!(" {} ", player.position_text()),
+            Style::new().black().on_light_green(),
+        ));
+        if player.finished() {
+            status.push(Span::styled(
+                " END ",
+                Style::new().black().on_yellow().bold(),
+            ));
+        }
+    }
+    if app.paused || player.is_some_and(|player| player.paused) {
+        status.push(Span::styled(
+            " PAUSED ",
+            Style::new().black().on_yellow().bold(),
+        ));
+    }
+    let keys = keys.into_iter().flatten();
+    // The replay position goes first so a narrow terminal cuts off key hints instead.
+    let spans = if player.is_some() {
+        status.push(Span::raw("  "));
+        status.into_iter().chain(keys).collect::<Vec<_>>()
+    } else {
+        keys.chain(status).collect()
+    };
+    frame.render_widget(Paragraph::new(Line::from(spans)), chunks[1]);
+}
+
+fn key_hint(key: &str, label: &str) -> [Span<'static>; 2] {
+    [
+        Span::styled(format!(" {} ", key), Style::new().black().on_cyan()),
+        Span::raw(format!(" {}  ", label)),
+    ]
+}
+
+fn draw_overview(frame: &mut Frame, area: Rect, states: &[Arc<Mutex<HostState>>], cursor: usize) {
+    let header = Row::new(["HOST", "LOAD", "CPU%", "MEM%", "FULLEST FS", "NET"])
+        .style(Style::new().yellow().bold());
+    let rows = states
+        .iter()
+        .map(|state| {
+            let state = state.lock().unwrap();
+            let name = if state.alerts.is_empty() {
+                Cell::from(state.name.clone())
+            } else {
+                Cell::from(format!("! {}", state.name)).light_red().bold()
+            };
+            match (&state.status, &state.stats) {
+                // The status text goes in the wide filesystem column, the gauges are blank.
+                (HostStatus::Failed(e), _) => Row::new(vec![
+                    name,
+                    Cell::from("error").red(),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(e.clone()).red(),
+                ]),
+                (HostStatus::Reconnecting { attempt, error }, _) => Row::new(vec![
+                    name,
+                    Cell::from(format!("retry {}", attempt)).yellow(),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(error.clone()).yellow(),
+                ]),
+                (_, Some(stats)) => {
+                    let fs = match stats.fullest_fs() {
+                        Some((mount_point, used)) => {
+                            Cell::from(format!("{} {:.0}%", mount_point, used))
+                                .style(percent_style(used))
+                        }
+                        None => Cell::from("-"),
+                    };
+                    Row::new(vec![
+                        name,
+                        Cell::from(stats.load1.clone()),
+                        Cell::from(format!("{:.1}", stats.cpu_usage()))
+                            .style(percent_style(stats.cpu_usage())),
+                        Cell::from(format!("{:.1}", stats.mem_usage()))
+                            .style(percent_style(stats.mem_usage())),
+                        fs,
+                        Cell::from(format!("{}/s", format_bytes(state.net_rate as u64))),
+                    ])
+                }
+                _ => Row::new(vec![
+                    name,
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from(""),
+                    Cell::from("connecting").dark_gray(),
+                ]),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let widths = [
+        Constraint::Percentage(30),
+        Constraint::Length(8),
+        Constraint::Length(7),
+        Constraint::Length(7),
+        Constraint::Percentage(25),
+        Constraint::Length(16),
+    ];
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(titled_block("Fleet overview"))
+        .highlight_style(Style::new().reversed());
+    let mut table_state = TableState::default().with_selected(Some(cursor));
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
+fn draw_host(frame: &mut Frame, area: Rect, state: &HostState, process_sort: ProcessSort) {
+    let core_count = state.stats.as_ref().map_or(0, |stats| stats.cores.len());
+    let core_columns = (area.width.saturating_sub(2) / CORE_CELL_WIDTH).max(1) as usize;
+    let core_rows = core_count.div_ceil(core_columns).min(MAX_CORE_ROWS) as u16;
+    let disk_count = state.stats.as_ref().map_or(0, |stats| stats.disks.len());
+    let disk_rows = disk_count.min(MAX_DISK_ROWS) as u16;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(11),
+            Constraint::Length(if core_rows > 0 { core_rows + 2 } else { 0 }),
+            Constraint::Length(15),
+            Constraint::Length(8),
+            Constraint::Length(if disk_rows > 0 { disk_rows + 3 } else { 0 }),
+            Constraint::Min(5),
+        ])
+        .split(area);
+
+    let mut header = vec![Span::styled(
+        state.name.clone(),
+        Style::new().light_green().bold(),
+    )];
+    match &state.status {
+        HostStatus::Failed(e) => {
+            header.push(Span::styled(format!("  error: {}", e), Style::new().red()))
+        }
+        HostStatus::Reconnecting { attempt, error } => header.push(Span::styled(
+            format!("  reconnecting (attempt {}): {}", attempt, error),
+            Style::new().yellow(),
+        )),
+        HostStatus::Connecting => header.push(Span::raw("  connecting")),
+        HostStatus::Up => {}
+    }
+    let Some(stats) = &state.stats else {
+        frame.render_widget(
+            Paragraph::new(Line::from(header)).block(Block::new().borders(Borders::ALL)),
+            chunks[0],
+        );
+        return;
+    };
+    header.extend([
+        Span::raw(format!("  {}", stats.hostname.trim())),
+        Span::styled("  up ", Style::new().yellow()),
+        Span::raw(stats.format_uptime()),
+        Span::styled("  load ", Style::new().yellow()),
+        Span::raw(format!("{} {} {}", stats.load1, stats.load5, stats.load10)),
+        Span::styled("  procs ", Style::new().yellow()),
+        Span::raw(format!(
+            "{} running of {}",
+            stats.running_procs,
+            stats.total_procs.trim()
+        )),
+    ]);
+    if !stats.failed.is_empty() {
+        let failed = stats
+            .failed
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        header.push(Span::styled(
+            format!("  failed: {}", failed.join("; ")),
+            Style::new().red(),
+        ));
+    }
+    if !state.alerts.is_empty() {
+        header.push(Span::styled(
+            format!("  ALERT: {}", state.alerts.join(", ")),
+            Style::new().white().on_red().bold(),
+        ));
+    }
+    for warning in &state.warnings {
+        header.push(Span::styled(
+            format!("  warning: {}", warning),
+            Style::new().yellow(),
+        ));
+    }
+    frame.render_widget(
+        Paragraph::new(Line::from(header)).block(Block::new().borders(Borders::ALL)),
+        chunks[0],
+    );
+
+    let top = split_panel(chunks[1], 30);
+    draw_cpu(frame, top, stats, &state.cpu_history);
+    if core_rows > 0 {
+        draw_cores(frame, chunks[2], stats, core_columns);
+    }
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(chunks[3]);
+    draw_memory(frame, middle[0], stats, &state.mem_history);
+    draw_filesystems(frame, middle[1], stats);
+    draw_network(frame, chunks[4], stats, state);
+    if disk_rows > 0 {
+        draw_disks(frame, chunks[5], stats);
+    }
+    draw_processes(frame, chunks[6], stats, process_sort);
+}
+
+fn draw_processes(frame: &mut Frame, area: Rect, stats: &Stats, sort: ProcessSort) {
+    let mut processes = stats.processes.clone();
+    processes::sort(&mut processes, sort);
+    let visible = area.height.saturating_sub(3) as usize;
+    let rows = processes
+        .iter()
+        .take(visible)
+        .map(|process| {
+            Row::new(vec![
+                Cell::from(process.pid.to_string()),
+                Cell::from(process.user.clone()),
+                Cell::from(process.state.to_string()),
+                Cell::from(format!("{:.1}", process.cpu)),
+                Cell::from(format_bytes(process.rss)),
+                Cell::from(process.command.clone()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let widths = [
+        Constraint::Length(8),
+        Constraint::Length(10),
+        Constraint::Length(2),
+        Constraint::Length(7),
+        Constraint::Length(11),
+        Constraint::Min(10),
+    ];
+    let sorted_by = match sort {
+        ProcessSort::Cpu => "CPU",
+        ProcessSort::Memory => "memory",
+        ProcessSort::Pid => "PID",
+    };
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new(["PID", "USER", "S", "CPU%", "RSS", "COMMAND"]).style(Style::new().yellow()),
+        )
+        .block(section_block(
+            &format!("Processes ({}, by {})", stats.processes.len(), sorted_by),
+            stats,
+            &["processes"],
+        ));
+    frame.render_widget(table, area);
+}
+
+/// Grid of per-core busy gauges, `columns` cells per row.
+fn draw_cores(frame: &mut Frame, area: Rect, stats: &Stats, columns: usize) {
+    let cells = stats
+        .cores
+        .iter()
+        .map(|(id, core)| {
+            Span::styled(
+                format!(
+                    "{:>5} {} {:>3.0}%  ",
+                    format!("cpu{}", id),
+                    usage_bar(core.busy(), 8),
+                    core.busy()
+                ),
+                percent_style(core.busy()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let lines = cells
+        .chunks(columns)
+        .map(|row| Line::from(row.to_vec()))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(lines).block(section_block(
+            &format!("Cores ({})", stats.cores.len()),
+            stats,
+            &["stat"],
+        )),
+        area,
+    );
+}
+
+/// Splits a panel into a fixed-width text column and a sparkline filling the rest.
+fn split_panel(area: Rect, text_width: u16) -> [Rect; 2] {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(text_width), Constraint::Min(0)])
+        .split(area);
+    [chunks[0], chunks[1]]
+}
+
+fn draw_cpu(frame: &mut Frame, area: [Rect; 2], stats: &Stats, history: &VecDeque<u64>) {
+    let cpu = &stats.cpu;
+    let lines = [
+        ("user", cpu.user),
+        ("system", cpu.system),
+        ("nice", cpu.nice),
+        ("idle", cpu.idle),
+        ("iowait", cpu.iowait),
+        ("hardirq", cpu.irq),
+        ("softirq", cpu.soft_irq),
+        ("steal", cpu.steal),
+        ("guest", cpu.guest),
+    ]
+    .into_iter()
+    .map(|(label, value)| Line::from(format!("{:<8} {:>6.1}%", label, value)))
+    .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(lines).block(section_block("CPU", stats, &["stat"])),
+        area[0],
+    );
+    draw_sparkline(
+        frame,
+        area[1],
+        &format!("CPU busy {:.1}%", stats.cpu_usage()),
+        history,
+        Some(100),
+    );
+}
+
+fn draw_memory(frame: &mut Frame, area: Rect, stats: &Stats, history: &VecDeque<u64>) {
+    let memory = &stats.memory;
+    let mut figures = vec![
+        ("total", memory.total()),
+        ("used", memory.used()),
+        ("available", memory.available()),
+        ("buff/cache", memory.buff_cache()),
+        ("shared", memory.shared()),
+        ("slab", memory.slab()),
+        ("dirty", memory.dirty()),
+        ("writeback", memory.writeback()),
+        ("committed", memory.committed()),
+    ];
+    if memory.huge_pages_total() > 0 {
+        figures.push(("huge free", memory.huge_pages_free()));
+    }
+    figures.push(("swap used", memory.swap_used()));
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(figures.len() as u16 + 2),
+            Constraint::Min(0),
+        ])
+        .split(area);
+    let lines = figures
+        .into_iter()
+        .map(|(label, value)| Line::from(format!("{:<10} {:>12}", label, format_bytes(value))))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(lines).block(section_block("Memory", stats, &["meminfo"])),
+        chunks[0],
+    );
+    draw_sparkline(
+        frame,
+        chunks[1],
+        &format!("used {:.1}%", stats.mem_usage()),
+        history,
+        Some(100),
+    );
+}
+
+fn draw_filesystems(frame: &mut Frame, area: Rect, stats: &Stats) {
+    let rows = stats
+        .fs_infos
+        .iter()
+        .map(|fs| {
+            let percent = fs.used_percent;
+            let inodes = match fs.inodes_percent() {
+                Some(inodes) => {
+                    Cell::from(format!("{:>3.0}%", inodes)).style(percent_style(inodes))
+                }
+                None => Cell::from("-"),
+            };
+            Row::new(vec![
+                Cell::from(fs.mount_point.clone()),
+                Cell::from(fs.fs_type.clone()),
+                Cell::from(format_bytes(fs.size)),
+                Cell::from(format_bytes(fs.free)),
+                Cell::from(format!("{} {:>3.0}%", usage_bar(percent, 10), percent))
+                    .style(percent_style(percent)),
+                inodes,
+            ])
+        })
+        .collect::<Vec<_>>();
+    let widths = [
+        Constraint::Min(10),
+        Constraint::Length(8),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(16),
+        Constraint::Length(6),
+    ];
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new(["MOUNT", "TYPE", "SIZE", "FREE", "USED", "INODES"])
+                .style(Style::new().yellow()),
+        )
+        .block(section_block("Filesystems", stats, &["filesystems"]));
+    frame.render_widget(table, area);
+}
+
+fn draw_disks(frame: &mut Frame, area: Rect, stats: &Stats) {
+    let rows = stats
+        .disks
+        .iter()
+        .map(|disk| {
+            Row::new(vec![
+                Cell::from(disk.name.clone()),
+                Cell::from(format!("{}/s", format_bytes(disk.read_rate as u64))),
+                Cell::from(format!("{}/s", format_bytes(disk.write_rate as u64))),
+                Cell::from(format!("{:.1}", disk.read_iops)),
+                Cell::from(format!("{:.1}", disk.write_iops)),
+                Cell::from(format!("{:.1} ms", disk.await_ms)),
+                Cell::from(format!("{} {:>3.0}%", usage_bar(disk.util, 10), disk.util))
+                    .style(percent_style(disk.util)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let widths = [
+        Constraint::Min(10),
+        Constraint::Length(13),
+        Constraint::Length(13),
+        Constraint::Length(9),
+        Constraint::Length(9),
+        Constraint::Length(10),
+        Constraint::Length(16),
+    ];
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new(["DEVICE", "READ/S", "WRITE/S", "R/S", "W/S", "AWAIT", "UTIL"])
+                .style(Style::new().yellow()),
+        )
+        .block(section_block("Disk I/O", stats, &["disks"]));
+    frame.render_widget(table, area);
+}
+
+fn draw_network(frame: &mut Frame, area: Rect, stats: &Stats, state: &HostState) {
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(area);
+    let mut interfaces = stats.net_intf.iter().collect::<Vec<_>>();
+    interfaces.sort_by(|a, b| a.0.cmp(b.0));
+    let rows = interfaces
+        .into_iter()
+        .map(|(name, intf)| {
+            Row::new(vec![
+                Cell::from(name.clone()),
+                Cell::from(intf.ipv4.clone()),
+                Cell::from(intf.ipv6.clone()),
+                Cell::from(format!("{}/s", format_bytes(intf.rx_rate as u64))),
+                Cell::from(format!("{}/s", format_bytes(intf.tx_rate as u64))),
+                Cell::from(format_bytes(intf.rx)),
+                Cell::from(format_bytes(intf.tx)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Length(18),
+        Constraint::Min(10),
+        Constraint::Length(13),
+        Constraint::Length(13),
+        Constraint::Length(11),
+        Constraint::Length(11),
+    ];
+    let table = Table::new(rows, widths)
+        .header(
+            Row::new([
+                "IFACE", "IPV4", "IPV6", "RX/S", "TX/S", "RX TOTAL", "TX TOTAL",
+            ])
+            .style(Style::new().yellow()),
+        )
+        .block(section_block(
+            "Network interfaces",
+            stats,
+            &["interfaces", "net/dev"],
+        ));
+    frame.render_widget(table, chunks[0]);
+    draw_sparkline(
+        frame,
+        chunks[1],
+        &format!("rx+tx {}/s", format_bytes(state.net_rate as u64)),
+        &state.net_history,
+        None,
+    );
+}
+
+/// Renders the newest samples that fit into `area`.
+fn draw_sparkline(
+    frame: &mut Frame,
+    area: Rect,
+    title: &str,
+    history: &VecDeque<u64>,
+    max: Option<u64>,
+) {
+    let width = area.width.saturating_sub(2) as usize;
+    let data = history
+        .iter()
+        .skip(history.len().saturating_sub(width))
+        .copied()
+        .collect::<Vec<_>>();
+    let mut sparkline = Sparkline::default()
+        .block(titled_block(title))
+        .data(&data)
+        .style(Style::new().cyan());
+    if let Some(max) = max {
+        sparkline = sparkline.max(max);
+    }
+    frame.render_widget(sparkline, area);
+}
+
+fn titled_block(title: &str) -> Block<'static> {
+    Block::new().borders(Borders::ALL).title(Span::styled(
+        title.to_string(),
+        Style::new().yellow().bold(),
+    ))
+}
+
+/// A panel of the collectors named in `sections`, with a red title when they
+/// failed in the last sample and the panel shows the figures from before.
+fn section_block(title: &str, stats: &Stats, sections: &[&str]) -> Block<'static> {
+    match stats.failure(sections) {
+        Some(_) => Block::new().borders(Borders::ALL).title(Span::styled(
+            format!("{} (failed)", title),
+            Style::new().light_red().bold(),
+        )),
+        None => titled_block(title),
+    }
+}
+
+fn percent_style(percent: f32) -> Style {
+    if percent >= 90.0 {
+        Style::new().light_red().bold()
+    } else if percent >= 70.0 {
+        Style::new().light_yellow()
+    } else {
+        Style::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Snapshot;
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        handle_key(app, KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn quits() {
+        let mut app = App::new(1, 1);
+        assert!(matches!(press(&mut app, KeyCode::Char('q')), Action::Quit));
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(matches!(handle_key(&mut app, ctrl_c), Action::Quit));
+        assert!(matches!(press(&mut app, KeyCode::Char('c')), Action::None));
+    }
+
+    #[test]
+    fn pauses_and_sorts() {
+        let mut app = App::new(1, 1);
+        press(&mut app, KeyCode::Char('p'));
+        assert!(app.paused);
+        press(&mut app, KeyCode::Char(' '));
+        assert!(!app.paused);
+
+        let mut sorts = Vec::new();
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Char('s'));
+            sorts.push(app.process_sort);
+        }
+        assert_eq!(
+            sorts,
+            [ProcessSort::Memory, ProcessSort::Pid, ProcessSort::Cpu]
+        );
+    }
+
+    #[test]
+    fn keeps_the_interval_in_range() {
+        let mut app = App::new(1, 0);
+        assert_eq!(app.interval, 1);
+        assert!(matches!(
+            press(&mut app, KeyCode::Char('-')),
+            Action::IntervalChanged
+        ));
+        assert_eq!(app.interval, 1);
+        press(&mut app, KeyCode::Char('+'));
+        press(&mut app, KeyCode::Char('='));
+        assert_eq!(app.interval, 3);
+
+        let mut app = App::new(1, MAX_INTERVAL);
+        press(&mut app, KeyCode::Char('+'));
+        assert_eq!(app.interval, MAX_INTERVAL);
+    }
+
+    #[test]
+    fn selects_hosts_in_the_overview() {
+        let mut app = App::new(3, 1);
+        assert_eq!(app.selected, None);
+        press(&mut app, KeyCode::Up);
+        assert_eq!(app.cursor, 0);
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Down);
+        }
+        assert_eq!(app.cursor, 2);
+        press(&mut app, KeyCode::Char('k'));
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.selected, Some(1));
+
+        // The host view keeps its selection until Esc.
+        press(&mut app, KeyCode::Down);
+        assert_eq!((app.selected, app.cursor), (Some(1), 1));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.selected, None);
+    }
+
+    #[test]
+    fn a_single_host_has_no_overview() {
+        let mut app = App::new(1, 1);
+        assert_eq!(app.selected, Some(0));
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.selected, Some(0));
+    }
+
+    #[test]
+    fn no_hosts_to_select() {
+        let mut app = App::new(0, 1);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!((app.selected, app.cursor), (None, 0));
+    }
+
+    fn player() -> Player {
+        let snapshots = (0..=120)
+            .step_by(5)
+            .map(|second| {
+                let mut snapshot = Snapshot::new("db1", &Stats::default());
+                snapshot.timestamp =
+                    format!("2024-05-01T10:{:02}:{:02}Z", second / 60, second % 60);
+                snapshot
+            })
+            .collect();
+        Player::new(snapshots, 1.0).unwrap()
+    }
+
+    fn press_replay(player: &mut Player, code: KeyCode) -> bool {
+        handle_replay_key(player, KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn replay_pauses_and_seeks() {
+        let mut player = player();
+        assert!(press_replay(&mut player, KeyCode::Char(' ')));
+        assert!(player.paused);
+        assert!(press_replay(&mut player, KeyCode::Char('p')));
+        assert!(!player.paused);
+
+        // "2024-05-01 10:00:20 UTC  00:00:20 / 00:02:00  x1"
+        let at =
+            |player: &Player| player.position_text().split("  ").nth(1).unwrap()[..8].to_string();
+        press_replay(&mut player, KeyCode::Right);
+        press_replay(&mut player, KeyCode::Char('l'));
+        assert_eq!(at(&player), "00:00:20");
+        press_replay(&mut player, KeyCode::Left);
+        assert_eq!(at(&player), "00:00:10");
+        press_replay(&mut player, KeyCode::PageDown);
+        assert_eq!(at(&player), "00:02:00");
+        assert!(player.finished());
+        press_replay(&mut player, KeyCode::Home);
+        assert_eq!(at(&player), "00:00:00");
+        press_replay(&mut player, KeyCode::End);
+        assert_eq!(at(&player), "00:02:00");
+        press_replay(&mut player, KeyCode::PageUp);
+        assert_eq!(at(&player), "00:00:00");
+    }
+
+    #[test]
+    fn replay_keeps_the_speed_in_range() {
+        let mut player = player();
+        for _ in 0..10 {
+            press_replay(&mut player, KeyCode::Char('+'));
+        }
+        assert_eq!(player.speed, replay::MAX_SPEED);
+        press_replay(&mut player, KeyCode::Char('-'));
+        assert_eq!(player.speed, replay::MAX_SPEED / 2.0);
+        for _ in 0..20 {
+            press_replay(&mut player, KeyCode::Char('-'));
+        }
+        assert_eq!(player.speed, replay::MIN_SPEED);
+    }
+
+    #[test]
+    fn other_keys_fall_through_to_the_app() {
+        let mut player = player();
+        for code in [
+            KeyCode::Char('q'),
+            KeyCode::Char('s'),
+            KeyCode::Enter,
+            KeyCode::Esc,
+        ] {
+            assert!(!press_replay(&mut player, code));
+        }
+    }
+}
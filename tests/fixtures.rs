@@ -0,0 +1,321 @@
+//! Runs the collectors over sample output and compares the parsed `Stats` with
+//! a golden summary, so parser changes show up as a diff without an SSH server.
+//!
+//! Each directory in tests/fixtures is one host. The samples there are
+//! synthetic: written by hand in the formats of each system's /proc and
+//! tools, not captured from real machines, so they guard the parsers against
+//! regressions on those formats but not against real output that differs from
+//! them. Captures from real hosts should replace them.
+//!
+//! Hosts reached by running commands have `sample1.out` and `sample2.out`,
+//! what the sample script prints a few seconds apart. A real host can be added
+//! by capturing them there:
+//!
+//! ```text
+//! cargo run -q --example sample_script | ssh host sh > sample1.out
+//! ```
+//!
+//! Hosts read over SFTP have `sample1` and `sample2` directories with the
+//! files the collectors read at each sample, and a `statvfs` table standing in
+//! for the statvfs of each mount.
+//! `expected.txt` is the summary of the last sample. After an intended change,
+//! rewrite it with `UPDATE_FIXTURES=1 cargo test --test fixtures` and review
+//! the diff.
+
+use remote_monitoring::collector::{self, default_collectors};
+use remote_monitoring::error::MonitorError;
+use remote_monitoring::stats::Stats;
+use remote_monitoring::transport::{FsUsage, Transport};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Answers every script with the next captured sample.
+struct Replay {
+    samples: VecDeque<String>,
+}
+
+impl Transport for Replay {
+    fn run(&mut self, _script: &str) -> Result<String, MonitorError> {
+        self.samples
+            .pop_front()
+            .ok_or_else(|| MonitorError::Channel("no samples left".to_string()))
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+        Err(MonitorError::Channel(format!("{} read without SFTP", path)))
+    }
+
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError> {
+        Err(MonitorError::Channel(format!(
+            "{} listed without SFTP",
+            path
+        )))
+    }
+}
+
+/// Reads a copy of a host's files, like `SftpReader`.
+struct Files {
+    root: PathBuf,
+    statvfs: String,
+}
+
+impl Files {
+    fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+}
+
+impl Transport for Files {
+    fn run(&mut self, _script: &str) -> Result<String, MonitorError> {
+        Err(MonitorError::Channel(
+            "commands can't be run over SFTP".to_string(),
+        ))
+    }
+
+    fn read_file(&mut self, path: &str) -> Result<String, MonitorError> {
+        fs::read_to_string(self.path(path)).map_err(|e| MonitorError::Read {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    fn list_dir(&mut self, path: &str) -> Result<Vec<String>, MonitorError> {
+        let entries = fs::read_dir(self.path(path)).map_err(|e| MonitorError::Read {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
+        let mut names = entries
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    // block_size blocks blocks_free blocks_available inodes inodes_free mount
+    fn fs_usage(&mut self, path: &str) -> Result<FsUsage, MonitorError> {
+        let fields = self
+            .statvfs
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .find(|fields| fields.len() > 6 && fields[6..].join(" ") == path)
+            .ok_or_else(|| MonitorError::Read {
+                path: path.to_string(),
+                message: "Permission denied".to_string(),
+            })?;
+        let number = |index: usize| fields[index].parse::<u64>().unwrap();
+        Ok(FsUsage {
+            block_size: number(0),
+            blocks: number(1),
+            blocks_free: number(2),
+            blocks_available: number(3),
+            inodes: number(4),
+            inodes_free: number(5),
+        })
+    }
+
+    fn runs_commands(&self) -> bool {
+        false
+    }
+}
+
+/// Everything parsed, one figure per line. Network rates are left out, they
+/// depend on when the samples were parsed rather than on their contents.
+fn summary(stats: &Stats) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "hostname {}", stats.hostname.trim());
+    let _ = writeln!(out, "uptime {} ({})", stats.uptime, stats.format_uptime());
+    let _ = writeln!(
+        out,
+        "load {} {} {}, {} running of {}",
+        stats.load1,
+        stats.load5,
+        stats.load10,
+        stats.running_procs,
+        stats.total_procs.trim()
+    );
+
+    let cpu = &stats.cpu;
+    let _ = writeln!(
+        out,
+        "cpu user {:.1} nice {:.1} system {:.1} idle {:.1} iowait {:.1} irq {:.1} softirq {:.1} steal {:.1} guest {:.1}",
+        cpu.user, cpu.nice, cpu.system, cpu.idle, cpu.iowait, cpu.irq, cpu.soft_irq, cpu.steal, cpu.guest
+    );
+    for (id, core) in &stats.cores {
+        let _ = writeln!(out, "core {} busy {:.1}", id, core.busy());
+    }
+
+    let memory = &stats.memory;
+    let _ = writeln!(
+        out,
+        "memory total {} used {} free {} available {}, {:.1}% used",
+        memory.total(),
+        memory.used(),
+        memory.free(),
+        memory.available(),
+        stats.mem_usage()
+    );
+    let _ = writeln!(
+        out,
+        "memory buffers {} cached {} shared {} slab {} ({} reclaimable)",
+        memory.buffers(),
+        memory.cached(),
+        memory.shared(),
+        memory.slab(),
+        memory.slab_reclaimable()
+    );
+    let _ = writeln!(
+        out,
+        "memory dirty {} writeback {} committed {} of {}, hugepages {} free of {}",
+        memory.dirty(),
+        memory.writeback(),
+        memory.committed(),
+        memory.commit_limit(),
+        memory.huge_pages_free(),
+        memory.huge_pages_total()
+    );
+    let _ = writeln!(
+        out,
+        "swap {} free of {}",
+        memory.swap_free(),
+        memory.swap_total()
+    );
+
+    for fs in &stats.fs_infos {
+        let _ = writeln!(
+            out,
+            "filesystem {} {} on {}: size {} used {} free {} ({:.1}%), inodes {} of {}",
+            fs.mount_point,
+            fs.fs_type,
+            fs.device,
+            fs.size,
+            fs.used,
+            fs.free,
+            fs.used_percent,
+            fs.inodes_used,
+            fs.inodes
+        );
+    }
+
+    for disk in &stats.disks {
+        let _ = writeln!(
+            out,
+            "disk {}{}: read {:.1}/s write {:.1}/s, {:.2} r/s {:.2} w/s, await {:.2}ms, util {:.1}%",
+            disk.name,
+            if disk.partition { " (partition)" } else { "" },
+            disk.read_rate,
+            disk.write_rate,
+            disk.read_iops,
+            disk.write_iops,
+            disk.await_ms,
+            disk.util
+        );
+    }
+
+    let mut interfaces = stats.net_intf.iter().collect::<Vec<_>>();
+    interfaces.sort_by_key(|(name, _)| name.as_str());
+    for (name, intf) in interfaces {
+        let _ = writeln!(
+            out,
+            "interface {}: ipv4 {:?} ipv6 {:?}, rx {} ({} packets) tx {} ({} packets)",
+            name, intf.ipv4, intf.ipv6, intf.rx, intf.rx_packets, intf.tx, intf.tx_packets
+        );
+    }
+
+    for process in &stats.processes {
+        let _ = writeln!(
+            out,
+            "process {} {} {} cpu {:.1} rss {}: {}",
+            process.pid, process.user, process.state, process.cpu, process.rss, process.command
+        );
+    }
+
+    for failed in &stats.failed {
+        let _ = writeln!(out, "failed {}", failed);
+    }
+    out
+}
+
+fn check(name: &str) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let read = |file: &str| {
+        fs::read_to_string(dir.join(file)).unwrap_or_else(|e| panic!("{}/{}: {}", name, file, e))
+    };
+
+    let mut collectors = default_collectors();
+    let mut stats = Stats::default();
+    if dir.join("sample1").is_dir() {
+        for sample in ["sample1", "sample2"] {
+            let mut files = Files {
+                root: dir.join(sample),
+                statvfs: read("statvfs"),
+            };
+            collector::collect(&mut collectors, &mut files, &mut stats).unwrap();
+        }
+    } else {
+        let mut replay = Replay {
+            samples: VecDeque::from([read("sample1.out"), read("sample2.out")]),
+        };
+        collector::collect(&mut collectors, &mut replay, &mut stats).unwrap();
+        collector::collect(&mut collectors, &mut replay, &mut stats).unwrap();
+    }
+
+    let actual = summary(&stats);
+    if std::env::var_os("UPDATE_FIXTURES").is_some() {
+        fs::write(dir.join("expected.txt"), &actual).unwrap();
+        return;
+    }
+    let expected = read("expected.txt");
+    assert!(
+        actual == expected,
+        "{} differs from expected.txt:\n{}",
+        name,
+        diff(&expected, &actual)
+    );
+}
+
+/// The lines only in `expected` (-) or only in `actual` (+).
+fn diff(expected: &str, actual: &str) -> String {
+    let mut out = String::new();
+    let only_in = |text: &str, other: &str| {
+        text.lines()
+            .filter(|line| !other.lines().any(|other| other == *line))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    for line in only_in(expected, actual) {
+        let _ = writeln!(out, "- {}", line);
+    }
+    for line in only_in(actual, expected) {
+        let _ = writeln!(out, "+ {}", line);
+    }
+    out
+}
+
+#[test]
+fn ubuntu_22_04() {
+    check("ubuntu-22.04");
+}
+
+#[test]
+fn rhel_6_4() {
+    check("rhel-6.4");
+}
+
+#[test]
+fn alpine_3_19() {
+    check("alpine-3.19");
+}
+
+#[test]
+fn debian_slim_container() {
+    check("debian-slim-container");
+}
+
+#[test]
+fn debian_12_sftp() {
+    check("debian-12-sftp");
+}